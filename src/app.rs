@@ -0,0 +1,689 @@
+use image::GenericImageView;
+use log::*;
+use std::time::{Duration, Instant};
+
+use eframe::{
+    egui,
+    epaint::{CircleShape, Color32, PathShape, Pos2, Stroke},
+};
+
+use crate::ballistics::Meter;
+use crate::classify::SoundModel;
+use crate::direction::{self, Direction};
+use crate::distance;
+use crate::dsp::BandFilter;
+use crate::events::{EventDetector, SoundEvent};
+use crate::geometry::{
+    bearing_point, sectors, INNER_RADIUS_FACTOR, OUTER_RADIUS, OVERHEAD_RADIUS_FACTOR, WINDOW_SIZE,
+};
+use crate::noise::NoiseFloor;
+use crate::radar::Scope;
+use crate::settings::Settings;
+use crate::source::{ChannelLayout, LevelSource, Speaker, Transport};
+use crate::spectrum::{Spectrum, PANEL_WIDTH};
+use crate::templates::TemplateMatcher;
+use crate::tracking::{Tracker, TRAIL};
+
+static TRANSPORT_HEIGHT: f32 = 32.;
+/// How far the arrow keys skip through a recording.
+static SKIP: Duration = Duration::from_secs(5);
+/// Lines drawn behind the sweep for its afterglow, and degrees between them.
+static AFTERGLOW_STEPS: usize = 12;
+static AFTERGLOW_SPACING: f32 = 2.;
+/// How long a sound event is marked on the radar after it finishes.
+static EVENT_MARK: Duration = Duration::from_secs(2);
+/// The innermost ring, where the nearest sounds are placed; the outermost
+/// ring is as far as can be told.
+static NEAREST_RING: f32 = 0.2;
+
+fn get_icon_data() -> Option<eframe::IconData> {
+    let bytes = include_bytes!("../icon/panopticon.png");
+
+    let image = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
+        .expect("Embedded icon must be a valid PNG");
+
+    Some(eframe::IconData {
+        width: image.dimensions().0,
+        height: image.dimensions().1,
+        rgba: image.into_bytes(),
+    })
+}
+
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub fn run_ui(
+    mut source: Box<dyn LevelSource>,
+    model: Option<Box<dyn SoundModel>>,
+    templates: TemplateMatcher,
+) {
+    let icon_data = get_icon_data();
+
+    let peak_values = vec![0.; source.channel_count()];
+    let meter = Meter::new(source.channel_count());
+    let noise_floor = NoiseFloor::new(source.channel_count());
+    let detector = EventDetector::new(source.channel_count(), model, templates);
+    let tracker = Tracker::new(source.sample_rate(), source.channel_count());
+    let spectrum = source
+        .sample_rate()
+        .map(|rate| Spectrum::new(rate, source.channel_count()));
+    let height = match source.transport() {
+        Some(_) => WINDOW_SIZE + TRANSPORT_HEIGHT,
+        None => WINDOW_SIZE,
+    };
+
+    let options = eframe::NativeOptions {
+        initial_window_size: Some(egui::vec2(WINDOW_SIZE, height)),
+        icon_data,
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Panopticon",
+        options,
+        Box::new(move |cc| {
+            Box::new(PanApp {
+                source,
+                peak_values,
+                error: None,
+                settings: Settings::load(cc.storage),
+                show_settings: false,
+                tracker,
+                scope: Scope::default(),
+                samples: Vec::new(),
+                filtered: Vec::new(),
+                filter: None,
+                spectrum,
+                height,
+                width: WINDOW_SIZE,
+                meter,
+                noise_floor,
+                detector,
+                event_marks: Vec::new(),
+                now: Instant::now(),
+                last_frame: None,
+            })
+        }),
+    );
+}
+
+struct PanApp {
+    source: Box<dyn LevelSource>,
+    peak_values: Vec<f32>,
+    /// The most recent failure to read the source, shown until a read succeeds.
+    error: Option<String>,
+    settings: Settings,
+    show_settings: bool,
+    tracker: Tracker,
+    scope: Scope,
+    /// Audio captured since the last frame, for sources that have it.
+    samples: Vec<f32>,
+    /// `samples` limited to the band of interest, for metering.
+    filtered: Vec<f32>,
+    filter: Option<BandFilter>,
+    /// Spectrum of each channel, for sources that have audio.
+    spectrum: Option<Spectrum>,
+    height: f32,
+    /// Width of the window, which grows to fit the spectrum panel.
+    width: f32,
+    meter: Meter,
+    noise_floor: NoiseFloor,
+    detector: EventDetector,
+    /// Recently finished sound events with a direction, and when they
+    /// finished.
+    event_marks: Vec<(Instant, SoundEvent)>,
+    /// The radar's clock, which stands still while a recording is paused.
+    now: Instant,
+    last_frame: Option<Instant>,
+}
+
+impl PanApp {
+    fn read_levels(&mut self) {
+        match self.source.read_levels(&mut self.peak_values) {
+            Ok(()) => self.error = None,
+            Err(e) => {
+                let message = format!("{:#}", e);
+                if self.error.as_ref() != Some(&message) {
+                    error!("Failed to read levels: {}", message);
+                }
+                self.peak_values.fill(0.);
+                self.error = Some(message);
+            }
+        }
+    }
+    /// Read and analyse everything the source captured since the last
+    /// frame, `elapsed` seconds ago.
+    fn analyse(&mut self, layout: &ChannelLayout, elapsed: f32) {
+        self.read_levels();
+        self.samples.clear();
+        self.source.read_samples(&mut self.samples);
+        if let Some(spectrum) = self
+            .spectrum
+            .as_mut()
+            .filter(|_| self.settings.spectrum.show)
+        {
+            spectrum.update(&self.samples);
+        }
+        self.filter_levels();
+        let now = self.now;
+        if self.settings.events.detect {
+            let samples = self
+                .source
+                .sample_rate()
+                .map(|rate| (self.samples.as_slice(), rate));
+            let finished = self.detector.update(
+                layout,
+                &self.settings.placement,
+                &self.settings.channel_map,
+                &self.peak_values,
+                samples,
+                elapsed,
+                &self.settings.events,
+            );
+            self.event_marks.extend(
+                finished
+                    .into_iter()
+                    .filter(|event| event.direction.is_some())
+                    .map(|event| (now, event)),
+            );
+        }
+        self.event_marks
+            .retain(|(time, _)| now - *time < EVENT_MARK);
+        if self.settings.noise.suppress {
+            self.noise_floor
+                .update(&mut self.peak_values, elapsed, &self.settings.noise);
+        }
+        self.meter
+            .update(&mut self.peak_values, elapsed, &self.settings.ballistics);
+        if self.settings.blip.track {
+            self.tracker.update(
+                now,
+                layout,
+                &self.settings.placement,
+                &self.settings.channel_map,
+                &self.peak_values,
+                &self.samples,
+                self.settings.filter.band(),
+                self.settings.blip.threshold(),
+                &self.settings.distance,
+            );
+        }
+    }
+
+    /// Limit a copy of the audio to the band of interest and meter that
+    /// instead of the source's broadband levels. Events are still detected
+    /// and classified on the broadband audio.
+    fn filter_levels(&mut self) {
+        self.filtered.clear();
+        self.filtered.extend_from_slice(&self.samples);
+        let (Some(band), Some(sample_rate)) =
+            (self.settings.filter.band(), self.source.sample_rate())
+        else {
+            self.filter = None;
+            return;
+        };
+        let channels = self.peak_values.len();
+        let filter = match &mut self.filter {
+            Some(filter) if filter.band() == band => filter,
+            filter => filter.insert(BandFilter::new(sample_rate, channels, band)),
+        };
+        filter.process(&mut self.filtered);
+
+        self.peak_values.fill(0.);
+        for frame in self.filtered.chunks_exact(channels) {
+            for (peak, sample) in self.peak_values.iter_mut().zip(frame) {
+                *peak = peak.max(sample.abs().min(1.));
+            }
+        }
+    }
+}
+
+/// Play/pause button and seek bar for sources playing a recording.
+fn transport_panel(ctx: &egui::Context, transport: &mut dyn Transport) {
+    let position = transport.position();
+    let duration = transport.duration();
+
+    {
+        let input = ctx.input();
+        if input.key_pressed(egui::Key::Space) {
+            transport.set_paused(!transport.is_paused());
+        }
+        if input.key_pressed(egui::Key::ArrowLeft) {
+            transport.seek(position.saturating_sub(SKIP));
+        }
+        if input.key_pressed(egui::Key::ArrowRight) {
+            let target = position + SKIP;
+            transport.seek(duration.map_or(target, |duration| target.min(duration)));
+        }
+    }
+
+    egui::TopBottomPanel::bottom("transport")
+        .exact_height(TRANSPORT_HEIGHT)
+        .show(ctx, |ui| {
+            ui.horizontal_centered(|ui| {
+                let label = if transport.is_paused() { "▶" } else { "⏸" };
+                if ui.button(label).clicked() {
+                    transport.set_paused(!transport.is_paused());
+                }
+
+                let time = match duration {
+                    Some(duration) => {
+                        format!("{} / {}", format_time(position), format_time(duration))
+                    }
+                    None => format_time(position),
+                };
+                if let Some(duration) = duration {
+                    let mut seconds = position.as_secs_f32();
+                    ui.spacing_mut().slider_width = ui.available_width() - 80.;
+                    let slider = egui::Slider::new(&mut seconds, 0.0..=duration.as_secs_f32())
+                        .show_value(false);
+                    if ui.add(slider).changed() {
+                        transport.seek(Duration::from_secs_f32(seconds));
+                    }
+                }
+                ui.label(time);
+            });
+        });
+}
+
+impl eframe::App for PanApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.settings.save(storage);
+    }
+
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let frame_time = Instant::now();
+        let elapsed = self
+            .last_frame
+            .map_or(0., |last| (frame_time - last).as_secs_f32());
+        self.last_frame = Some(frame_time);
+        let layout = self.source.layout().clone();
+        // A paused recording holds the whole picture, as it was when paused
+        let paused = self
+            .source
+            .transport()
+            .is_some_and(|transport| transport.is_paused());
+        if !paused {
+            self.now += Duration::from_secs_f32(elapsed);
+            self.analyse(&layout, elapsed);
+        }
+        let now = self.now;
+
+        let sectors = sectors(&layout, &self.settings.placement);
+        let mut routed: Vec<Speaker> = sectors.iter().map(|(s, _)| *s).collect();
+        if layout.position(Speaker::LowFrequency).is_some() {
+            routed.push(Speaker::LowFrequency);
+        }
+        egui::Window::new("Settings")
+            .open(&mut self.show_settings)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                self.settings
+                    .ui(ui, &layout, &routed, self.source.sample_rate().is_some())
+            });
+
+        let clock = self.detector.clock();
+        egui::Window::new("Events")
+            .open(&mut self.settings.events.show_log)
+            .vscroll(true)
+            .show(ctx, |ui| {
+                if self.detector.events().is_empty() {
+                    ui.label("No sound events yet");
+                }
+                egui::Grid::new("events").striped(true).show(ui, |ui| {
+                    for event in self.detector.events().iter().rev() {
+                        ui.label(format!("{:.1}s ago", (clock - event.time).as_secs_f32()));
+                        ui.colored_label(
+                            event.category.color(),
+                            format!(
+                                "{} {}",
+                                event.category.icon(),
+                                event.class.as_deref().unwrap_or(event.category.label())
+                            ),
+                        );
+                        ui.label(match event.direction {
+                            Some(direction) => format!("{:.0}°", direction.azimuth),
+                            None => "–".to_string(),
+                        });
+                        ui.label(format!("{:.1} dBFS", event.loudness_db));
+                        ui.label(format!("{} ms", event.duration.as_millis()));
+                        ui.end_row();
+                    }
+                });
+            });
+
+        if let Some(transport) = self.source.transport() {
+            transport_panel(ctx, transport);
+        }
+
+        let width = if self.settings.spectrum.show {
+            WINDOW_SIZE + PANEL_WIDTH
+        } else {
+            WINDOW_SIZE
+        };
+        if width != self.width {
+            frame.set_window_size(egui::vec2(width, self.height));
+            self.width = width;
+        }
+        if self.settings.spectrum.show {
+            egui::SidePanel::right("spectrum")
+                .exact_width(PANEL_WIDTH)
+                .resizable(false)
+                .show(ctx, |ui| match &mut self.spectrum {
+                    Some(spectrum) => spectrum.ui(
+                        ui,
+                        &layout,
+                        &mut self.settings.spectrum,
+                        &self.settings.scale,
+                        self.settings.filter.band(),
+                    ),
+                    None => {
+                        ui.label("This source only reports levels, so has no spectrum");
+                    }
+                });
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let painter = ui.painter();
+            let scale = &self.settings.scale;
+
+            for (speaker, shape) in &sectors {
+                let meter = scale.scale(self.settings.channel_map.level(
+                    &layout,
+                    &self.peak_values,
+                    *speaker,
+                ));
+                // Overhead sectors glow amber so height reads at a glance
+                let fill = if speaker.is_overhead() {
+                    Color32::from_rgb((meter * 255.) as u8, (meter * 160.) as u8, 0)
+                } else {
+                    Color32::from_rgba_premultiplied((meter * 255.) as u8, 0, 0, 255)
+                };
+                painter.add(PathShape {
+                    points: shape.clone(),
+                    closed: true,
+                    fill,
+                    stroke: Stroke {
+                        width: 1.,
+                        color: Color32::BLACK,
+                    },
+                });
+
+                // Held peak along the outer edge of the sector
+                let peak = scale.scale(self.settings.channel_map.level(
+                    &layout,
+                    self.meter.peaks(),
+                    *speaker,
+                ));
+                if peak > 0. {
+                    let outer_arc = shape[..shape.len() / 2].to_vec();
+                    painter.add(PathShape::line(
+                        outer_arc,
+                        Stroke {
+                            width: 2.,
+                            color: Color32::from_rgb(255, (peak * 255.) as u8, (peak * 128.) as u8)
+                                .linear_multiply(peak),
+                        },
+                    ));
+                }
+            }
+
+            // The LFE channel, if any, pulses the inner disc
+            let lfe = &self.settings.lfe;
+            let lfe_fill = match layout.position(Speaker::LowFrequency) {
+                Some(_) if lfe.show => lfe.color(
+                    self.settings.channel_map.level(
+                        &layout,
+                        &self.peak_values,
+                        Speaker::LowFrequency,
+                    ),
+                    scale,
+                ),
+                _ => Color32::BLACK,
+            };
+
+            // Concentric rings, which mark relative distance when sounds are
+            // placed by it
+            for factor in [1., 0.8, 0.6, 0.4, 0.2] {
+                painter.add(CircleShape {
+                    radius: OUTER_RADIUS * INNER_RADIUS_FACTOR * factor,
+                    fill: if factor == 1. {
+                        lfe_fill
+                    } else {
+                        Color32::TRANSPARENT
+                    },
+                    stroke: Stroke {
+                        width: 1.,
+                        color: Color32::GREEN,
+                    },
+                    center: Pos2 {
+                        x: WINDOW_SIZE / 2.,
+                        y: WINDOW_SIZE / 2.,
+                    },
+                });
+            }
+
+            // Horitontal Radar axis
+            painter.add(PathShape {
+                points: vec![
+                    Pos2 {
+                        x: WINDOW_SIZE / 2. - (OUTER_RADIUS * INNER_RADIUS_FACTOR),
+                        y: WINDOW_SIZE / 2.,
+                    },
+                    Pos2 {
+                        x: WINDOW_SIZE / 2. + (OUTER_RADIUS * INNER_RADIUS_FACTOR),
+                        y: WINDOW_SIZE / 2.,
+                    },
+                ],
+                stroke: Stroke {
+                    width: 1.,
+                    color: Color32::GREEN,
+                },
+                closed: false,
+                fill: Color32::TRANSPARENT,
+            });
+
+            // Verical Radar axis
+            painter.add(PathShape {
+                points: vec![
+                    Pos2 {
+                        x: WINDOW_SIZE / 2.,
+                        y: WINDOW_SIZE / 2. - (OUTER_RADIUS * INNER_RADIUS_FACTOR),
+                    },
+                    Pos2 {
+                        x: WINDOW_SIZE / 2.,
+                        y: WINDOW_SIZE / 2. + (OUTER_RADIUS * INNER_RADIUS_FACTOR),
+                    },
+                ],
+                stroke: Stroke {
+                    width: 1.,
+                    color: Color32::GREEN,
+                },
+                closed: false,
+                fill: Color32::TRANSPARENT,
+            });
+
+            let blip = &self.settings.blip;
+            let sweep = &self.settings.sweep;
+
+            // The sounds to show, with their track IDs when tracking
+            let sounds: Vec<(Option<u32>, Direction, f32)> = if !blip.show {
+                vec![]
+            } else if blip.track {
+                self.tracker
+                    .tracks()
+                    .iter()
+                    .map(|track| (Some(track.id), track.direction, track.distance))
+                    .collect()
+            } else {
+                direction::estimate(
+                    &layout,
+                    &self.settings.placement,
+                    &self.settings.channel_map,
+                    &self.peak_values,
+                    blip.threshold(),
+                )
+                .map(|direction| {
+                    // Empty for peak meter sources, which leaves the cue out
+                    let direct_ratio_db = distance::direct_ratio_db(
+                        &layout,
+                        &self.settings.placement,
+                        &self.settings.channel_map,
+                        &self.samples,
+                        direction.azimuth,
+                    );
+                    let distance = distance::estimate(
+                        &direction,
+                        None,
+                        direct_ratio_db,
+                        &self.settings.distance,
+                    );
+                    (None, direction, distance)
+                })
+                .into_iter()
+                .collect()
+            };
+            self.scope
+                .sweep(now, sweep.speed, sweep.persistence(), &sounds);
+
+            // Sweeping hand, with an afterglow fading behind it
+            let center = Pos2 {
+                x: WINDOW_SIZE / 2.,
+                y: WINDOW_SIZE / 2.,
+            };
+            for step in (0..AFTERGLOW_STEPS).rev() {
+                let fade = 1. - step as f32 / AFTERGLOW_STEPS as f32;
+                let bearing = self.scope.bearing() - step as f32 * AFTERGLOW_SPACING;
+                painter.add(PathShape {
+                    points: vec![center, bearing_point(bearing, INNER_RADIUS_FACTOR)],
+                    stroke: Stroke {
+                        width: 2.,
+                        color: Color32::from_rgba_unmultiplied(144, 238, 144, (fade * 255.) as u8),
+                    },
+                    closed: false,
+                    fill: Color32::TRANSPARENT,
+                });
+            }
+
+            // Blips at the estimated directions of sounds, within the rings
+            // by their estimated distance or else beside the sectors, sized
+            // by their level and faded as they spread between speakers
+            let sector_radius = if sectors.iter().any(|(s, _)| s.is_overhead()) {
+                (OVERHEAD_RADIUS_FACTOR + 1.) / 2.
+            } else {
+                (INNER_RADIUS_FACTOR + 1.) / 2.
+            };
+            let blip_radius = |distance: f32| {
+                if self.settings.distance.show {
+                    INNER_RADIUS_FACTOR * (NEAREST_RING + (1. - NEAREST_RING) * distance)
+                } else {
+                    sector_radius
+                }
+            };
+            let draw_blip =
+                |id: Option<u32>, direction: &Direction, distance: f32, brightness: f32| {
+                    let alpha = (64. + direction.focus * 191.) * brightness;
+                    let center = bearing_point(direction.azimuth, blip_radius(distance));
+                    painter.add(CircleShape {
+                        center,
+                        radius: 3. + scale.scale(direction.level) * 5.,
+                        fill: Color32::from_rgba_unmultiplied(144, 238, 144, alpha as u8),
+                        stroke: Stroke {
+                            width: 1.,
+                            color: Color32::from_white_alpha((brightness * 255.) as u8),
+                        },
+                    });
+                    if let Some(id) = id {
+                        painter.text(
+                            center + egui::vec2(6., -6.),
+                            egui::Align2::LEFT_BOTTOM,
+                            id,
+                            egui::FontId::monospace(10.),
+                            Color32::from_white_alpha((brightness * 255.) as u8),
+                        );
+                    }
+                };
+            if sweep.scan {
+                for echo in self.scope.echoes() {
+                    let brightness = echo.brightness(now, sweep.persistence());
+                    draw_blip(echo.id, &echo.direction, echo.distance, brightness);
+                }
+            } else {
+                if blip.track {
+                    // Trail of fading dots where each track has been
+                    for track in self.tracker.tracks() {
+                        for (time, azimuth, distance) in &track.trail {
+                            let age = (now - *time).as_secs_f32() / TRAIL.as_secs_f32();
+                            painter.add(CircleShape {
+                                center: bearing_point(*azimuth, blip_radius(*distance)),
+                                radius: 2.,
+                                fill: Color32::from_rgba_unmultiplied(
+                                    144,
+                                    238,
+                                    144,
+                                    ((1. - age).max(0.) * 128.) as u8,
+                                ),
+                                stroke: Stroke::NONE,
+                            });
+                        }
+                    }
+                }
+                for (id, direction, distance) in &sounds {
+                    draw_blip(*id, direction, *distance, 1.);
+                }
+            }
+
+            // Ticks on the rim where sound events just happened, coloured
+            // and marked by what they seemed to be, and named when they
+            // were recognised
+            for (time, event) in &self.event_marks {
+                let Some(direction) = event.direction else {
+                    continue;
+                };
+                let azimuth = direction.azimuth;
+                let fade = 1. - (now - *time).as_secs_f32() / EVENT_MARK.as_secs_f32();
+                let color = event.category.color().linear_multiply(fade);
+                painter.add(PathShape::line(
+                    vec![bearing_point(azimuth, 0.92), bearing_point(azimuth, 1.)],
+                    Stroke { width: 3., color },
+                ));
+                let icon = bearing_point(azimuth, 0.82);
+                painter.text(
+                    icon,
+                    egui::Align2::CENTER_CENTER,
+                    event.category.icon(),
+                    egui::FontId::proportional(14.),
+                    color,
+                );
+                if let Some(class) = &event.class {
+                    painter.text(
+                        icon + egui::vec2(0., 10.),
+                        egui::Align2::CENTER_TOP,
+                        class,
+                        egui::FontId::proportional(10.),
+                        color,
+                    );
+                }
+            }
+
+            ui.horizontal(|ui| {
+                if ui.small_button("⚙").clicked() {
+                    self.show_settings = !self.show_settings;
+                }
+                if ui.small_button("📈").clicked() {
+                    self.settings.spectrum.show = !self.settings.spectrum.show;
+                }
+                if ui.small_button("📋").clicked() {
+                    self.settings.events.show_log = !self.settings.events.show_log;
+                }
+            });
+
+            if let Some(error) = &self.error {
+                ui.colored_label(Color32::RED, error);
+            }
+        });
+
+        ctx.request_repaint_after(std::time::Duration::from_millis(33));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::settings::BallisticsSettings;
+
+/// Per channel meter state, smoothing raw levels so that short transients
+/// stay on screen long enough to see.
+pub struct Meter {
+    levels: Vec<f32>,
+    peaks: Vec<f32>,
+    /// Seconds since each peak was last reached.
+    held: Vec<f32>,
+}
+
+impl Meter {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            levels: vec![0.; channels],
+            peaks: vec![0.; channels],
+            held: vec![0.; channels],
+        }
+    }
+
+    /// Held peak of each channel, or zeros with peak hold off.
+    pub fn peaks(&self) -> &[f32] {
+        &self.peaks
+    }
+
+    /// Advance the meter by `elapsed` seconds given raw `levels`, which are
+    /// replaced by the metered ones.
+    pub fn update(&mut self, levels: &mut [f32], elapsed: f32, settings: &BallisticsSettings) {
+        // One pole smoothing towards the input, reaching 63% of a step in
+        // the attack or release time
+        let coefficient = |time_ms: f32| {
+            if time_ms <= 0. {
+                1.
+            } else {
+                1. - (-elapsed * 1000. / time_ms).exp()
+            }
+        };
+        let attack = coefficient(settings.attack_ms);
+        let release = coefficient(settings.release_ms);
+        let decay = 10f32.powf(-settings.decay_db_per_s * elapsed / 20.);
+
+        for (((input, level), peak), held) in levels
+            .iter_mut()
+            .zip(&mut self.levels)
+            .zip(&mut self.peaks)
+            .zip(&mut self.held)
+        {
+            let speed = if *input > *level { attack } else { release };
+            *level += (*input - *level) * speed;
+            *input = *level;
+
+            if !settings.peak_hold {
+                *peak = 0.;
+            } else if *level >= *peak {
+                *peak = *level;
+                *held = 0.;
+            } else {
+                *held += elapsed;
+                if *held * 1000. > settings.hold_ms {
+                    *peak = (*peak * decay).max(*level);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_and_release_reach_63_percent() {
+        let settings = BallisticsSettings::default();
+        let mut meter = Meter::new(1);
+        let mut levels = [1.];
+        meter.update(&mut levels, settings.attack_ms / 1000., &settings);
+        assert!((levels[0] - (1. - (-1f32).exp())).abs() < 1e-3);
+
+        for _ in 0..50 {
+            levels[0] = 1.;
+            meter.update(&mut levels, 0.01, &settings);
+        }
+        assert!((levels[0] - 1.).abs() < 1e-3);
+        levels[0] = 0.;
+        meter.update(&mut levels, settings.release_ms / 1000., &settings);
+        assert!((levels[0] - (-1f32).exp()).abs() < 1e-3, "{}", levels[0]);
+    }
+
+    #[test]
+    fn zero_attack_follows_immediately() {
+        let settings = BallisticsSettings {
+            attack_ms: 0.,
+            ..Default::default()
+        };
+        let mut meter = Meter::new(1);
+        let mut levels = [0.7];
+        meter.update(&mut levels, 0.01, &settings);
+        assert_eq!(levels[0], 0.7);
+    }
+
+    #[test]
+    fn peak_holds_then_decays() {
+        let settings = BallisticsSettings {
+            attack_ms: 0.,
+            release_ms: 0.,
+            ..Default::default()
+        };
+        let mut meter = Meter::new(1);
+        meter.update(&mut [1.], 0.01, &settings);
+        meter.update(&mut [0.], settings.hold_ms / 1000. - 0.05, &settings);
+        assert_eq!(meter.peaks()[0], 1.);
+
+        // Past the hold it falls at the decay rate
+        for _ in 0..10 {
+            meter.update(&mut [0.], 0.01, &settings);
+        }
+        let held = meter.peaks()[0];
+        assert!(held < 1.);
+        for _ in 0..100 {
+            meter.update(&mut [0.], 0.01, &settings);
+        }
+        let fall_db = 20. * (held / meter.peaks()[0]).log10();
+        assert!(
+            (fall_db - settings.decay_db_per_s).abs() < 1e-3,
+            "{}",
+            fall_db
+        );
+    }
+
+    #[test]
+    fn no_peaks_without_peak_hold() {
+        let settings = BallisticsSettings {
+            peak_hold: false,
+            ..Default::default()
+        };
+        let mut meter = Meter::new(2);
+        meter.update(&mut [1., 0.5], 0.01, &settings);
+        assert_eq!(meter.peaks(), &[0., 0.]);
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::Result;
+use eframe::epaint::Color32;
+use std::time::Duration;
+
+use crate::dsp::PowerSpectrum;
+
+/// Samples per FFT when measuring the spectrum of a sound.
+static FFT_SIZE: usize = 1024;
+/// Window the envelope is measured over to find the attack.
+static ENVELOPE_WINDOW: Duration = Duration::from_millis(1);
+/// Bands the spectrum is split into, in Hz.
+static LOW_BAND: f32 = 200.;
+static VOICE_BAND: (f32, f32) = (300., 3400.);
+static HIGH_BAND: f32 = 4000.;
+/// Sounds at least this long are background rather than single sounds.
+static AMBIENT_DURATION: Duration = Duration::from_millis(1500);
+
+/// What kind of sound an event was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    Footsteps,
+    Gunshot,
+    Explosion,
+    Voice,
+    Ambient,
+    Unknown,
+}
+
+impl Category {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Category::Footsteps => "Footsteps",
+            Category::Gunshot => "Gunshot",
+            Category::Explosion => "Explosion",
+            Category::Voice => "Voice",
+            Category::Ambient => "Ambient",
+            Category::Unknown => "Unknown",
+        }
+    }
+
+    /// The category a sound model's class names, matched by label, or
+    /// unknown for classes specific to a game.
+    pub fn from_class(class: &str) -> Self {
+        [
+            Category::Footsteps,
+            Category::Gunshot,
+            Category::Explosion,
+            Category::Voice,
+            Category::Ambient,
+        ]
+        .into_iter()
+        .find(|category| category.label().eq_ignore_ascii_case(class.trim()))
+        .unwrap_or(Category::Unknown)
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Category::Footsteps => "👣",
+            Category::Gunshot => "🔫",
+            Category::Explosion => "💥",
+            Category::Voice => "💬",
+            Category::Ambient => "〰",
+            Category::Unknown => "❓",
+        }
+    }
+
+    pub fn color(&self) -> Color32 {
+        match self {
+            Category::Footsteps => Color32::from_rgb(0, 200, 255),
+            Category::Gunshot => Color32::from_rgb(255, 60, 60),
+            Category::Explosion => Color32::from_rgb(255, 160, 0),
+            Category::Voice => Color32::from_rgb(200, 120, 255),
+            Category::Ambient => Color32::from_rgb(128, 128, 128),
+            Category::Unknown => Color32::from_rgb(255, 255, 0),
+        }
+    }
+}
+
+/// A trained model that recognises sounds from their audio.
+pub trait SoundModel {
+    /// The class the interleaved `clip` of `channels` channels, starting at
+    /// the sound's onset, most resembles, with its score.
+    fn predict(&mut self, clip: &[f32], channels: usize, sample_rate: u32)
+        -> Result<(String, f32)>;
+}
+
+/// Spectral and temporal features of a sound.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Features {
+    pub duration: Duration,
+    pub loudness_db: f32,
+    /// Time from the onset to the loudest point.
+    pub attack: Duration,
+    /// Centre of mass of the power spectrum in Hz.
+    pub centroid: f32,
+    /// Fractions of the power below `LOW_BAND`, within `VOICE_BAND` and
+    /// above `HIGH_BAND`.
+    pub low: f32,
+    pub voice: f32,
+    pub high: f32,
+    /// Spectral flatness, from 0 for a pure tone to 1 for white noise.
+    pub flatness: f32,
+}
+
+impl Features {
+    /// Measure the mono `clip` recorded from a sound's onset.
+    pub fn measure(clip: &[f32], sample_rate: u32, duration: Duration, loudness_db: f32) -> Self {
+        let window_frames = ((ENVELOPE_WINDOW.as_secs_f32() * sample_rate as f32) as usize).max(1);
+        let loudest = clip
+            .chunks(window_frames)
+            .map(|window| window.iter().map(|s| s * s).sum::<f32>())
+            .enumerate()
+            .fold((0, 0.), |loudest, (i, power)| {
+                if power > loudest.1 {
+                    (i, power)
+                } else {
+                    loudest
+                }
+            })
+            .0;
+        let attack = Duration::from_secs_f32((loudest * window_frames) as f32 / sample_rate as f32);
+
+        // Average power spectrum over half overlapping Hann windows
+        let mut spectrum = PowerSpectrum::new(FFT_SIZE);
+        let mut power = vec![0f32; FFT_SIZE / 2 + 1];
+        let mut start = 0;
+        loop {
+            for (sum, bin) in power.iter_mut().zip(spectrum.process(&clip[start..])) {
+                *sum += bin;
+            }
+            start += FFT_SIZE / 2;
+            if start + FFT_SIZE > clip.len() {
+                break;
+            }
+        }
+
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let band = |low: f32, high: f32| -> f32 {
+            power
+                .iter()
+                .enumerate()
+                .filter(|(bin, _)| (low..high).contains(&(*bin as f32 * bin_width)))
+                .map(|(_, power)| power)
+                .sum()
+        };
+        // Ignore DC, which says nothing about the sound
+        let total = band(bin_width, f32::INFINITY).max(1e-12);
+        let centroid = power
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(bin, power)| bin as f32 * bin_width * power)
+            .sum::<f32>()
+            / total;
+
+        let audible: Vec<f32> = power
+            .iter()
+            .enumerate()
+            .filter(|(bin, _)| (100.0..8000.).contains(&(*bin as f32 * bin_width)))
+            .map(|(_, power)| power.max(1e-12))
+            .collect();
+        let flatness = if audible.is_empty() {
+            0.
+        } else {
+            let count = audible.len() as f32;
+            let geometric = (audible.iter().map(|power| power.ln()).sum::<f32>() / count).exp();
+            let arithmetic = audible.iter().sum::<f32>() / count;
+            geometric / arithmetic
+        };
+
+        Self {
+            duration,
+            loudness_db,
+            attack,
+            centroid,
+            low: band(bin_width, LOW_BAND) / total,
+            voice: band(VOICE_BAND.0, VOICE_BAND.1) / total,
+            high: band(HIGH_BAND, f32::INFINITY) / total,
+            flatness,
+        }
+    }
+
+    /// Guess what kind of sound has these features.
+    pub fn classify(&self) -> Category {
+        let millis = self.duration.as_millis();
+        if self.voice > 0.6 && self.flatness < 0.3 && millis >= 150 {
+            // Harmonic sound in the speech band
+            Category::Voice
+        } else if self.low > 0.4 && millis >= 250 && self.loudness_db > -30. {
+            // Loud, long and dominated by rumble
+            Category::Explosion
+        } else if self.attack.as_millis() <= 15
+            && millis < 600
+            && self.centroid > 1000.
+            && self.flatness > 0.2
+            && self.loudness_db > -30.
+        {
+            // Sharp, loud, broadband crack
+            Category::Gunshot
+        } else if millis < 300 && self.centroid < 2000. && self.high < 0.3 {
+            // Short, dull thud
+            Category::Footsteps
+        } else if self.duration >= AMBIENT_DURATION {
+            Category::Ambient
+        } else {
+            Category::Unknown
+        }
+    }
+}
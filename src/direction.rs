@@ -0,0 +1,204 @@
+use crate::mapping::ChannelMap;
+use crate::placement::SpeakerPlacement;
+use crate::source::ChannelLayout;
+
+/// The dominant direction of the sound field, found by inverting amplitude
+/// panning: the energy (Gerzon) vector of the speaker directions weighted by
+/// the squared level of each.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Direction {
+    /// Degrees clockwise from straight ahead, in -180..=180.
+    pub azimuth: f32,
+    /// Length of the energy vector, 1 for a sound from a single speaker and
+    /// towards 0 as it spreads around the listener.
+    pub focus: f32,
+    /// Combined level of the directional channels, in 0..=1.
+    pub level: f32,
+}
+
+/// The energy vector of speakers given as `(azimuth, energy)` pairs.
+fn energy_vector(speakers: impl IntoIterator<Item = (f32, f32)>) -> Option<Direction> {
+    let (mut x, mut y, mut energy) = (0., 0., 0.);
+    for (azimuth, speaker_energy) in speakers {
+        let (sin, cos) = azimuth.to_radians().sin_cos();
+        x += speaker_energy * sin;
+        y += speaker_energy * cos;
+        energy += speaker_energy;
+    }
+    if energy <= 0. {
+        return None;
+    }
+    Some(Direction {
+        azimuth: x.atan2(y).to_degrees(),
+        focus: (x * x + y * y).sqrt() / energy,
+        level: energy.sqrt().min(1.),
+    })
+}
+
+/// The directional speakers of `layout` as `(azimuth, energy)` pairs, in
+/// order round the listener.
+fn ring(
+    layout: &ChannelLayout,
+    placement: &SpeakerPlacement,
+    channel_map: &ChannelMap,
+    levels: &[f32],
+) -> Vec<(f32, f32)> {
+    let mut ring: Vec<(f32, f32)> = layout
+        .speakers()
+        .iter()
+        .filter_map(|speaker| {
+            let azimuth = placement.azimuth(*speaker)?.rem_euclid(360.);
+            let level = channel_map.level(layout, levels, *speaker);
+            Some((azimuth, level * level))
+        })
+        .collect();
+    ring.sort_by(|a, b| a.0.total_cmp(&b.0));
+    ring
+}
+
+/// Estimate the direction of the sound given per channel `levels`, or `None`
+/// if the directional channels are quieter than `threshold`.
+pub fn estimate(
+    layout: &ChannelLayout,
+    placement: &SpeakerPlacement,
+    channel_map: &ChannelMap,
+    levels: &[f32],
+    threshold: f32,
+) -> Option<Direction> {
+    energy_vector(ring(layout, placement, channel_map, levels))
+        .filter(|direction| direction.level >= threshold)
+}
+
+/// Estimate the directions of separate sounds given per channel `levels`.
+///
+/// Each speaker louder than its neighbours round the listener is taken to
+/// carry a sound of its own, panned between it and those neighbours. A
+/// neighbour lying between two such speakers is shared between them.
+pub fn separate(
+    layout: &ChannelLayout,
+    placement: &SpeakerPlacement,
+    channel_map: &ChannelMap,
+    levels: &[f32],
+    threshold: f32,
+) -> Vec<Direction> {
+    let ring = ring(layout, placement, channel_map, levels);
+    let count = ring.len();
+    if count < 3 {
+        return estimate(layout, placement, channel_map, levels, threshold)
+            .into_iter()
+            .collect();
+    }
+
+    let energy = |i: usize| ring[i % count].1;
+    // Ties go to the earlier speaker, so a plateau yields a single peak
+    let peaks: Vec<bool> = (0..count)
+        .map(|i| energy(i) > 0. && energy(i) > energy(i + count - 1) && energy(i) >= energy(i + 1))
+        .collect();
+    let is_peak = |i: usize| peaks[i % count];
+    let share = |i: usize| {
+        let claims = is_peak(i + count - 1) as u8 + is_peak(i + 1) as u8;
+        energy(i) / claims.max(1) as f32
+    };
+
+    (0..count)
+        .filter(|i| is_peak(*i))
+        .filter_map(|i| {
+            let before = (i + count - 1) % count;
+            let after = (i + 1) % count;
+            energy_vector([
+                (ring[before].0, share(before)),
+                (ring[i].0, energy(i)),
+                (ring[after].0, share(after)),
+            ])
+        })
+        .filter(|direction| direction.level >= threshold)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Speaker::{self, *};
+
+    /// Levels for `layout` with the given speakers sounding.
+    fn levels(layout: &ChannelLayout, sounding: &[(Speaker, f32)]) -> Vec<f32> {
+        layout
+            .speakers()
+            .iter()
+            .map(|speaker| {
+                sounding
+                    .iter()
+                    .find(|(s, _)| s == speaker)
+                    .map_or(0., |(_, level)| *level)
+            })
+            .collect()
+    }
+
+    fn estimate_7_1(sounding: &[(Speaker, f32)], threshold: f32) -> Option<Direction> {
+        let layout = ChannelLayout::surround_7_1();
+        let levels = levels(&layout, sounding);
+        estimate(
+            &layout,
+            &SpeakerPlacement::default(),
+            &ChannelMap::default(),
+            &levels,
+            threshold,
+        )
+    }
+
+    #[test]
+    fn pan_between_front_and_side_right() {
+        let direction = estimate_7_1(&[(FrontRight, 0.65), (SideRight, 0.76)], 0.).unwrap();
+        assert!((direction.azimuth - 65.).abs() < 1., "{:?}", direction);
+        assert!(direction.focus < 1.);
+        assert!((direction.level - 1.).abs() < 0.01);
+    }
+
+    #[test]
+    fn single_speaker_is_focused_on_it() {
+        let direction = estimate_7_1(&[(SideLeft, 0.5)], 0.).unwrap();
+        assert!((direction.azimuth + 90.).abs() < 1e-3, "{:?}", direction);
+        assert!((direction.focus - 1.).abs() < 1e-6);
+        assert!((direction.level - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn silence_has_no_direction() {
+        assert_eq!(estimate_7_1(&[], 0.), None);
+        assert_eq!(estimate_7_1(&[(FrontLeft, 0.01)], 0.05), None);
+    }
+
+    #[test]
+    fn quiet_sounds_are_not_separated() {
+        let layout = ChannelLayout::surround_7_1();
+        let levels = levels(&layout, &[(FrontLeft, 0.01), (BackRight, 0.02)]);
+        let sounds = separate(
+            &layout,
+            &SpeakerPlacement::default(),
+            &ChannelMap::default(),
+            &levels,
+            0.05,
+        );
+        assert!(sounds.is_empty(), "{:?}", sounds);
+    }
+
+    #[test]
+    fn separates_sounds_on_opposite_sides() {
+        let layout = ChannelLayout::surround_7_1();
+        let levels = levels(&layout, &[(FrontLeft, 0.5), (BackRight, 0.5)]);
+        let mut azimuths: Vec<f32> = separate(
+            &layout,
+            &SpeakerPlacement::default(),
+            &ChannelMap::default(),
+            &levels,
+            0.05,
+        )
+        .iter()
+        .map(|sound| sound.azimuth)
+        .collect();
+        azimuths.sort_by(f32::total_cmp);
+        assert_eq!(azimuths.len(), 2);
+        assert!((azimuths[0] + 30.).abs() < 1e-3, "{:?}", azimuths);
+        assert!((azimuths[1] - 150.).abs() < 1e-3, "{:?}", azimuths);
+    }
+}
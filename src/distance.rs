@@ -0,0 +1,235 @@
+use crate::direction::Direction;
+use crate::mapping::ChannelMap;
+use crate::placement::SpeakerPlacement;
+use crate::settings::DistanceSettings;
+use crate::source::ChannelLayout;
+
+/// Octave bands compared to measure how much of the top end a sound has
+/// lost, which air absorbs more of over distance.
+static MID_BANDS: (f32, f32) = (500., 2000.);
+static HIGH_BANDS: f32 = 4000.;
+/// Level of the high bands relative to the mid bands, in dB, taken as a
+/// nearby sound and as a distant one.
+static NEAR_TILT_DB: f32 = -6.;
+static FAR_TILT_DB: f32 = -30.;
+/// Direct to reverberant ratio, in dB, taken as a nearby sound and as a
+/// distant one. Diffuse reverberation across two speakers alone reads 0dB.
+static NEAR_DRR_DB: f32 = 15.;
+static FAR_DRR_DB: f32 = 0.;
+/// Power iterations taken to find the strongest coherent part of a sound.
+static ITERATIONS: usize = 16;
+/// How much each cue counts towards the estimate.
+static LOUDNESS_WEIGHT: f32 = 0.5;
+static REVERB_WEIGHT: f32 = 0.25;
+static ROLLOFF_WEIGHT: f32 = 0.25;
+
+/// Level of the high octave bands relative to the mid ones, in dB, of the
+/// sound at `azimuth`, weighting each speaker by how nearly it faces the
+/// sound. `bands` holds the level of each channel in each band centred on
+/// `centres`.
+pub fn rolloff_db(
+    layout: &ChannelLayout,
+    placement: &SpeakerPlacement,
+    channel_map: &ChannelMap,
+    centres: &[f32],
+    bands: &[Vec<f32>],
+    azimuth: f32,
+) -> Option<f32> {
+    let energy = |band: &[f32]| -> f32 {
+        layout
+            .speakers()
+            .iter()
+            .filter_map(|speaker| {
+                let weight = (placement.azimuth(*speaker)? - azimuth).to_radians().cos();
+                let level = channel_map.level(layout, band, *speaker);
+                Some(weight.max(0.) * level * level)
+            })
+            .sum()
+    };
+    let (mut mid, mut high) = (0., 0.);
+    for (centre, band) in centres.iter().zip(bands) {
+        if (MID_BANDS.0..=MID_BANDS.1).contains(centre) {
+            mid += energy(band);
+        } else if *centre >= HIGH_BANDS {
+            high += energy(band);
+        }
+    }
+    (mid > 0.).then(|| 10. * (high / mid).max(1e-6).log10())
+}
+
+/// Ratio, in dB, of the coherent part of the interleaved broadband
+/// `samples` facing the sound at `azimuth` to the rest, standing in for its
+/// direct to reverberant ratio. A sound's direct path reaches the speakers
+/// as one signal, however it is panned, while its reverberation is mixed
+/// decorrelated across them, so the strongest principal component of the
+/// speakers' covariance is taken as direct and the rest as reverberant.
+/// `None` with fewer than two speakers facing the sound, or silence.
+pub fn direct_ratio_db(
+    layout: &ChannelLayout,
+    placement: &SpeakerPlacement,
+    channel_map: &ChannelMap,
+    samples: &[f32],
+    azimuth: f32,
+) -> Option<f32> {
+    // Channels facing the sound, and the factor applied to each's samples
+    let facing: Vec<(usize, f32)> = layout
+        .speakers()
+        .iter()
+        .filter_map(|speaker| {
+            let weight = (placement.azimuth(*speaker)? - azimuth).to_radians().cos();
+            let channel = channel_map.channel(layout, *speaker)?;
+            let factor = channel_map.trim(channel).factor() * weight.max(0.).sqrt();
+            (factor != 0.).then_some((channel, factor))
+        })
+        .collect();
+    let n = facing.len();
+    if n < 2 {
+        return None;
+    }
+
+    let mut covariance = vec![vec![0.; n]; n];
+    for frame in samples.chunks_exact(layout.len()) {
+        for (row, (a, fa)) in covariance.iter_mut().zip(&facing) {
+            for (cell, (b, fb)) in row.iter_mut().zip(&facing) {
+                *cell += frame[*a] * fa * frame[*b] * fb;
+            }
+        }
+    }
+    let total: f32 = (0..n).map(|i| covariance[i][i]).sum();
+    if total <= 0. {
+        return None;
+    }
+
+    // Power iteration from the loudest channel, whose Rayleigh quotient only
+    // grows towards the largest eigenvalue
+    let loudest = (0..n).max_by(|a, b| covariance[*a][*a].total_cmp(&covariance[*b][*b]))?;
+    let mut vector = vec![0.; n];
+    vector[loudest] = 1.;
+    let mut direct = covariance[loudest][loudest];
+    for _ in 0..ITERATIONS {
+        let next: Vec<f32> = covariance
+            .iter()
+            .map(|row| row.iter().zip(&vector).map(|(c, v)| c * v).sum())
+            .collect();
+        let norm = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm <= 0. {
+            break;
+        }
+        vector = next.into_iter().map(|v| v / norm).collect();
+        direct = covariance
+            .iter()
+            .zip(&vector)
+            .map(|(row, v)| v * row.iter().zip(&vector).map(|(c, w)| c * w).sum::<f32>())
+            .sum::<f32>()
+            .max(direct);
+    }
+    let reverberant = (total - direct).max(total * 1e-6);
+    Some(10. * (direct / reverberant).log10())
+}
+
+/// Relative distance of a sound from 0, right by the listener, to 1, as far
+/// as can be told, from its loudness and, when known, its `direct_ratio_db`
+/// and high frequency `rolloff_db`, both of which should be measured on
+/// broadband audio.
+pub fn estimate(
+    direction: &Direction,
+    rolloff_db: Option<f32>,
+    direct_ratio_db: Option<f32>,
+    settings: &DistanceSettings,
+) -> f32 {
+    let level_db = 20. * direction.level.max(1e-9).log10();
+    let loudness =
+        ((settings.near_db - level_db) / (settings.near_db - settings.far_db)).clamp(0., 1.);
+
+    let mut total = loudness * LOUDNESS_WEIGHT;
+    let mut weight = LOUDNESS_WEIGHT;
+    if let Some(direct_ratio_db) = direct_ratio_db {
+        let reverb = ((NEAR_DRR_DB - direct_ratio_db) / (NEAR_DRR_DB - FAR_DRR_DB)).clamp(0., 1.);
+        total += reverb * REVERB_WEIGHT;
+        weight += REVERB_WEIGHT;
+    }
+    if let Some(rolloff_db) = rolloff_db {
+        let rolloff = ((NEAR_TILT_DB - rolloff_db) / (NEAR_TILT_DB - FAR_TILT_DB)).clamp(0., 1.);
+        total += rolloff * ROLLOFF_WEIGHT;
+        weight += ROLLOFF_WEIGHT;
+    }
+    total / weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Speaker;
+
+    static RATE: f32 = 48000.;
+
+    /// A second of 7.1 with `signal` giving each channel's sample at a time.
+    fn capture(signal: impl Fn(usize, f32) -> f32) -> (ChannelLayout, Vec<f32>) {
+        let layout = ChannelLayout::surround_7_1();
+        let samples = (0..RATE as usize)
+            .flat_map(|i| {
+                let t = i as f32 / RATE;
+                (0..layout.len()).map(move |channel| (channel, t))
+            })
+            .map(|(channel, t)| signal(channel, t))
+            .collect();
+        (layout, samples)
+    }
+
+    fn tone(frequency: f32, t: f32) -> f32 {
+        (std::f32::consts::TAU * frequency * t).sin()
+    }
+
+    #[test]
+    fn dry_panned_sound_is_direct() {
+        let layout = ChannelLayout::surround_7_1();
+        let left = layout.position(Speaker::FrontLeft).unwrap();
+        let centre = layout.position(Speaker::FrontCenter).unwrap();
+        let (layout, samples) = capture(|channel, t| match channel {
+            c if c == left => 0.5 * tone(440., t),
+            c if c == centre => 0.3 * tone(440., t),
+            _ => 0.,
+        });
+        let ratio = direct_ratio_db(
+            &layout,
+            &SpeakerPlacement::default(),
+            &ChannelMap::default(),
+            &samples,
+            -15.,
+        )
+        .unwrap();
+        assert!(ratio > NEAR_DRR_DB, "{}", ratio);
+    }
+
+    #[test]
+    fn decorrelated_sound_is_reverberant() {
+        // Tones a whole number of cycles apart are uncorrelated over a second
+        let (layout, samples) = capture(|channel, t| 0.3 * tone(300. + 70. * channel as f32, t));
+        let ratio = direct_ratio_db(
+            &layout,
+            &SpeakerPlacement::default(),
+            &ChannelMap::default(),
+            &samples,
+            0.,
+        )
+        .unwrap();
+        assert!(ratio < FAR_DRR_DB, "{}", ratio);
+    }
+
+    #[test]
+    fn reverberation_pushes_sounds_away() {
+        let direction = Direction {
+            azimuth: 0.,
+            level: 0.1,
+            focus: 1.,
+        };
+        let settings = DistanceSettings::default();
+        let dry = estimate(&direction, None, Some(NEAR_DRR_DB), &settings);
+        let wet = estimate(&direction, None, Some(FAR_DRR_DB), &settings);
+        assert!(wet > dry);
+        assert_eq!(estimate(&direction, None, None, &settings), {
+            let level_db = 20. * direction.level.log10();
+            (settings.near_db - level_db) / (settings.near_db - settings.far_db)
+        });
+    }
+}
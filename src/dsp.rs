@@ -0,0 +1,193 @@
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+/// A second order IIR filter section, with coefficients from the Audio EQ
+/// Cookbook.
+#[derive(Clone, Debug)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// Band-pass around `centre` Hz with unity gain at the centre.
+    pub fn band_pass(sample_rate: u32, centre: f32, q: f32) -> Self {
+        let w0 = 2. * PI * centre / sample_rate as f32;
+        let alpha = w0.sin() / (2. * q);
+        let a0 = 1. + alpha;
+        Self {
+            b0: alpha / a0,
+            b1: 0.,
+            b2: -alpha / a0,
+            a1: -2. * w0.cos() / a0,
+            a2: (1. - alpha) / a0,
+            z1: 0.,
+            z2: 0.,
+        }
+    }
+
+    /// Low-pass with a corner at `cutoff` Hz; a `q` of 0.707 is Butterworth.
+    pub fn low_pass(sample_rate: u32, cutoff: f32, q: f32) -> Self {
+        let w0 = 2. * PI * cutoff / sample_rate as f32;
+        let alpha = w0.sin() / (2. * q);
+        let a0 = 1. + alpha;
+        Self {
+            b0: (1. - w0.cos()) / 2. / a0,
+            b1: (1. - w0.cos()) / a0,
+            b2: (1. - w0.cos()) / 2. / a0,
+            a1: -2. * w0.cos() / a0,
+            a2: (1. - alpha) / a0,
+            z1: 0.,
+            z2: 0.,
+        }
+    }
+
+    /// High-pass with a corner at `cutoff` Hz; a `q` of 0.707 is Butterworth.
+    pub fn high_pass(sample_rate: u32, cutoff: f32, q: f32) -> Self {
+        let w0 = 2. * PI * cutoff / sample_rate as f32;
+        let alpha = w0.sin() / (2. * q);
+        let a0 = 1. + alpha;
+        Self {
+            b0: (1. + w0.cos()) / 2. / a0,
+            b1: -(1. + w0.cos()) / a0,
+            b2: (1. + w0.cos()) / 2. / a0,
+            a1: -2. * w0.cos() / a0,
+            a2: (1. - alpha) / a0,
+            z1: 0.,
+            z2: 0.,
+        }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        // Transposed direct form II
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+/// Q of a Butterworth second order section.
+static BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+/// Lowest corner frequency in Hz; a high-pass at DC is unstable.
+static MIN_CORNER: f32 = 10.;
+
+/// Limits each channel of interleaved audio to a band of frequencies.
+pub struct BandFilter {
+    band: (f32, f32),
+    channels: usize,
+    /// High-pass and low-pass for each channel.
+    sections: Vec<(Biquad, Biquad)>,
+}
+
+impl BandFilter {
+    /// A filter passing `band`, from its low to its high corner in Hz. The
+    /// corners are kept below Nyquist, and the low one below the high one.
+    pub fn new(sample_rate: u32, channels: usize, band: (f32, f32)) -> Self {
+        let (low, high) = band;
+        let high = high.clamp(MIN_CORNER * 2., sample_rate as f32 * 0.45);
+        let low = low.clamp(MIN_CORNER, high / 2.);
+        let sections = (
+            Biquad::high_pass(sample_rate, low, BUTTERWORTH_Q),
+            Biquad::low_pass(sample_rate, high, BUTTERWORTH_Q),
+        );
+        Self {
+            band,
+            channels,
+            sections: vec![sections; channels],
+        }
+    }
+
+    pub fn band(&self) -> (f32, f32) {
+        self.band
+    }
+
+    /// Filter interleaved `samples` in place.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(self.channels) {
+            for (sample, (high_pass, low_pass)) in frame.iter_mut().zip(&mut self.sections) {
+                *sample = low_pass.process(high_pass.process(*sample));
+            }
+        }
+    }
+}
+
+/// Power spectra of Hann windowed frames of audio.
+pub struct PowerSpectrum {
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    power: Vec<f32>,
+}
+
+impl PowerSpectrum {
+    /// Spectra of `size` samples, with `size / 2 + 1` bins.
+    pub fn new(size: usize) -> Self {
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(size);
+        let window = (0..size)
+            .map(|i| 0.5 - 0.5 * (2. * PI * i as f32 / size as f32).cos())
+            .collect();
+        Self {
+            input: fft.make_input_vec(),
+            output: fft.make_output_vec(),
+            power: vec![0.; size / 2 + 1],
+            fft,
+            window,
+        }
+    }
+
+    /// The power in each bin of the first `size` of `samples`, padded with
+    /// silence if there are fewer.
+    pub fn process<'a>(&mut self, samples: impl IntoIterator<Item = &'a f32>) -> &[f32] {
+        let mut samples = samples.into_iter();
+        for (input, window) in self.input.iter_mut().zip(&self.window) {
+            *input = samples.next().copied().unwrap_or(0.) * window;
+        }
+        // Only fails if the buffers are the wrong length
+        self.fft.process(&mut self.input, &mut self.output).unwrap();
+        for (power, bin) in self.power.iter_mut().zip(&self.output) {
+            *power = bin.norm_sqr();
+        }
+        &self.power
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RMS of a sine at `frequency` Hz after one second through `filter`.
+    fn response(filter: &mut BandFilter, sample_rate: u32, frequency: f32) -> f32 {
+        let mut samples: Vec<f32> = (0..sample_rate)
+            .map(|i| (2. * PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect();
+        filter.process(&mut samples);
+        let tail = &samples[samples.len() / 2..];
+        (tail.iter().map(|sample| sample * sample).sum::<f32>() / tail.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn corners_above_nyquist_are_clamped() {
+        let mut filter = BandFilter::new(8000, 1, (6000., 20000.));
+        let level = response(&mut filter, 8000, 3000.);
+        assert!(level.is_finite() && level > 0.1, "{}", level);
+        // The requested band is still reported, for comparing with settings
+        assert_eq!(filter.band(), (6000., 20000.));
+    }
+
+    #[test]
+    fn inverted_corners_are_clamped() {
+        let mut filter = BandFilter::new(48000, 1, (2000., 500.));
+        let level = response(&mut filter, 48000, 400.);
+        assert!(level.is_finite() && level > 0.1, "{}", level);
+        let mut filter = BandFilter::new(48000, 1, (0., 1000.));
+        assert!(response(&mut filter, 48000, 100.).is_finite());
+    }
+}
@@ -0,0 +1,396 @@
+use log::*;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::classify::{Category, Features, SoundModel};
+use crate::direction::{self, Direction};
+use crate::mapping::ChannelMap;
+use crate::placement::SpeakerPlacement;
+use crate::settings::EventSettings;
+use crate::source::ChannelLayout;
+use crate::templates::TemplateMatcher;
+
+/// Length of the steps audio is measured in; onsets are placed to within this.
+static STEP: Duration = Duration::from_millis(5);
+/// Time constants of the short and long term power compared to find onsets.
+static FAST: f32 = 0.01;
+static SLOW: f32 = 0.3;
+/// A channel's part of an event ends once it falls this far below its peak.
+static RELEASE_DB: f32 = 12.;
+/// Onsets on different channels this close together are one sound.
+static GROUP_WINDOW: Duration = Duration::from_millis(50);
+/// Longest event; anything longer is background rather than an event.
+static MAX_DURATION: Duration = Duration::from_secs(5);
+/// Audio kept from each event's onset for classifying it.
+static MAX_CLIP: Duration = Duration::from_secs(1);
+/// Finished events kept for display.
+static HISTORY: usize = 100;
+
+/// A discrete sound, from its onset until it died away.
+#[derive(Clone, Debug)]
+pub struct SoundEvent {
+    /// When the sound started, since the detector was created.
+    pub time: Duration,
+    pub duration: Duration,
+    /// Where the sound came from, if it reached any directional channel.
+    pub direction: Option<Direction>,
+    /// Peak level of the loudest channel in dBFS.
+    pub loudness_db: f32,
+    /// What the sound seemed to be; always unknown without audio.
+    pub category: Category,
+    /// The template or sound model class the sound was recognised as.
+    pub class: Option<String>,
+}
+
+/// An event some channels are still sounding.
+struct OpenEvent {
+    id: u64,
+    time: Duration,
+    peaks: Vec<f32>,
+    /// Interleaved audio since the onset, up to `MAX_CLIP`.
+    clip: Vec<f32>,
+}
+
+/// Finds onsets on each channel by comparing short and long term power, and
+/// groups those close together into sound events.
+pub struct EventDetector {
+    clock: Duration,
+    fast: Vec<f32>,
+    slow: Vec<f32>,
+    /// The open event each channel is sounding, if any.
+    sounding: Vec<Option<u64>>,
+    open: Vec<OpenEvent>,
+    next_id: u64,
+    events: VecDeque<SoundEvent>,
+    /// Recognises sounds in place of the heuristics, if the user gave one.
+    model: Option<Box<dyn SoundModel>>,
+    /// Recordings of specific sounds, recognised before anything else.
+    templates: TemplateMatcher,
+}
+
+impl EventDetector {
+    pub fn new(
+        channels: usize,
+        model: Option<Box<dyn SoundModel>>,
+        templates: TemplateMatcher,
+    ) -> Self {
+        Self {
+            clock: Duration::ZERO,
+            fast: vec![0.; channels],
+            slow: vec![0.; channels],
+            sounding: vec![None; channels],
+            open: Vec::new(),
+            next_id: 0,
+            events: VecDeque::new(),
+            model,
+            templates,
+        }
+    }
+
+    /// Recently finished events, oldest first.
+    pub fn events(&self) -> &VecDeque<SoundEvent> {
+        &self.events
+    }
+
+    pub fn clock(&self) -> Duration {
+        self.clock
+    }
+
+    /// Measure one step of `elapsed` seconds with per channel `levels`.
+    fn step(&mut self, levels: &[f32], elapsed: f32, settings: &EventSettings) {
+        self.clock += Duration::from_secs_f32(elapsed);
+        let fast = 1. - (-elapsed / FAST).exp();
+        let slow = 1. - (-elapsed / SLOW).exp();
+        let ratio = 10f32.powf(settings.sensitivity_db / 10.);
+        let min_power = 10f32.powf(settings.min_level_db / 10.);
+        let release = 10f32.powf(-RELEASE_DB / 10.);
+
+        for (channel, level) in levels.iter().enumerate() {
+            let power = level * level;
+            self.fast[channel] += (power - self.fast[channel]) * fast;
+            self.slow[channel] += (power - self.slow[channel]) * slow;
+            let fast = self.fast[channel];
+
+            match self.sounding[channel] {
+                Some(id) => {
+                    let Some(event) = self.open.iter_mut().find(|event| event.id == id) else {
+                        continue;
+                    };
+                    let peak = &mut event.peaks[channel];
+                    *peak = peak.max(fast.sqrt());
+                    let too_long = self.clock - event.time > MAX_DURATION;
+                    if fast < *peak * *peak * release || fast < min_power || too_long {
+                        self.sounding[channel] = None;
+                    }
+                }
+                None if fast > self.slow[channel] * ratio && fast > min_power => {
+                    // Join an event that has just started on another channel
+                    let clock = self.clock;
+                    let id = match self
+                        .open
+                        .iter()
+                        .rev()
+                        .find(|event| clock - event.time < GROUP_WINDOW)
+                    {
+                        Some(event) => event.id,
+                        None => {
+                            self.open.push(OpenEvent {
+                                id: self.next_id,
+                                time: clock,
+                                peaks: vec![0.; levels.len()],
+                                clip: Vec::new(),
+                            });
+                            self.next_id += 1;
+                            self.next_id - 1
+                        }
+                    };
+                    self.sounding[channel] = Some(id);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Detect events in the interleaved `samples` since the last update, or
+    /// in `levels` over `elapsed` seconds for sources without audio, after
+    /// the trims of `channel_map`. Returns the events that finished.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        layout: &ChannelLayout,
+        placement: &SpeakerPlacement,
+        channel_map: &ChannelMap,
+        levels: &[f32],
+        samples: Option<(&[f32], u32)>,
+        elapsed: f32,
+        settings: &EventSettings,
+    ) -> Vec<SoundEvent> {
+        let channels = levels.len();
+        // A channel muted or turned down for the radar is for events too
+        match samples {
+            Some((samples, sample_rate)) => {
+                // Trim the audio itself, so that inverted channels cancel
+                // where they are mixed
+                let mut trimmed = samples.to_vec();
+                channel_map.apply(&mut trimmed, channels);
+                let step_frames = ((STEP.as_secs_f32() * sample_rate as f32) as usize).max(1);
+                let mut step = vec![0.; channels];
+                for block in trimmed.chunks(step_frames * channels) {
+                    let frames = block.len() / channels;
+                    if frames == 0 {
+                        continue;
+                    }
+                    // RMS of each channel over the step
+                    step.fill(0.);
+                    for frame in block.chunks_exact(channels) {
+                        for (sum, sample) in step.iter_mut().zip(frame) {
+                            *sum += sample * sample;
+                        }
+                    }
+                    for sum in &mut step {
+                        *sum = (*sum / frames as f32).sqrt();
+                    }
+                    self.step(&step, frames as f32 / sample_rate as f32, settings);
+
+                    let max_clip =
+                        (MAX_CLIP.as_secs_f32() * sample_rate as f32) as usize * channels;
+                    for event in &mut self.open {
+                        let room = max_clip.saturating_sub(event.clip.len());
+                        event.clip.extend(&block[..block.len().min(room)]);
+                    }
+                }
+            }
+            None => {
+                let levels: Vec<f32> = levels
+                    .iter()
+                    .enumerate()
+                    .map(|(channel, level)| {
+                        (level * channel_map.trim(channel).factor().abs()).min(1.)
+                    })
+                    .collect();
+                self.step(&levels, elapsed, settings)
+            }
+        }
+
+        // Events no channel is sounding any more are finished
+        let (finished, open): (Vec<_>, Vec<_>) = std::mem::take(&mut self.open)
+            .into_iter()
+            .partition(|event| !self.sounding.contains(&Some(event.id)));
+        self.open = open;
+
+        let sample_rate = samples.map(|(_, sample_rate)| sample_rate);
+        // Peaks are already trimmed
+        let routes = channel_map.untrimmed();
+        let finished: Vec<SoundEvent> = finished
+            .into_iter()
+            .map(|event| {
+                let loudest = event
+                    .peaks
+                    .iter()
+                    .fold(0f32, |loudest, peak| loudest.max(*peak));
+                let duration = self.clock - event.time;
+                let loudness_db = 20. * loudest.max(1e-9).log10();
+                let (category, class) = match sample_rate {
+                    Some(sample_rate) if !event.clip.is_empty() => {
+                        let mono: Vec<f32> = event
+                            .clip
+                            .chunks_exact(channels)
+                            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                            .collect();
+                        let template = self
+                            .templates
+                            .best_match(&mono, sample_rate, settings.match_threshold)
+                            .map(|(name, _)| name.to_string());
+                        let class = template.or_else(|| {
+                            let model = self.model.as_mut()?;
+                            model
+                                .predict(&event.clip, channels, sample_rate)
+                                .map_err(|e| warn!("Failed to classify sound event: {:#}", e))
+                                .ok()
+                                .filter(|(_, score)| *score >= settings.min_model_score)
+                                .map(|(class, _)| class)
+                        });
+                        match class {
+                            Some(class) => (Category::from_class(&class), Some(class)),
+                            None => {
+                                let features =
+                                    Features::measure(&mono, sample_rate, duration, loudness_db);
+                                (features.classify(), None)
+                            }
+                        }
+                    }
+                    _ => (Category::Unknown, None),
+                };
+                SoundEvent {
+                    time: event.time,
+                    duration,
+                    direction: direction::estimate(layout, placement, &routes, &event.peaks, 0.),
+                    loudness_db,
+                    category,
+                    class,
+                }
+            })
+            .collect();
+        for event in &finished {
+            debug!("Sound event {:?}", event);
+            if self.events.len() == HISTORY {
+                self.events.pop_front();
+            }
+            self.events.push_back(event.clone());
+        }
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Background level on every channel, below the default minimum level.
+    static QUIET: f32 = 0.001;
+
+    struct Fixture {
+        detector: EventDetector,
+        layout: ChannelLayout,
+        settings: EventSettings,
+    }
+
+    impl Fixture {
+        fn stereo() -> Self {
+            Self {
+                detector: EventDetector::new(2, None, TemplateMatcher::default()),
+                layout: ChannelLayout::default_for(2).unwrap(),
+                settings: EventSettings::default(),
+            }
+        }
+
+        /// Feed `levels` for `duration` in steps, returning the events that
+        /// finished.
+        fn run(&mut self, levels: [f32; 2], duration: Duration) -> Vec<SoundEvent> {
+            let mut finished = Vec::new();
+            let steps = (duration.as_secs_f32() / STEP.as_secs_f32()).round() as usize;
+            for _ in 0..steps {
+                finished.extend(self.detector.update(
+                    &self.layout,
+                    &SpeakerPlacement::default(),
+                    &ChannelMap::default(),
+                    &levels,
+                    None,
+                    STEP.as_secs_f32(),
+                    &self.settings,
+                ));
+            }
+            finished
+        }
+    }
+
+    fn secs(seconds: f32) -> Duration {
+        Duration::from_secs_f32(seconds)
+    }
+
+    #[test]
+    fn step_makes_one_event_ended_by_its_release() {
+        let mut fixture = Fixture::stereo();
+        assert!(fixture.run([QUIET, QUIET], secs(1.)).is_empty());
+        // Nothing finishes while the sound is held
+        assert!(fixture.run([0.5, QUIET], secs(0.2)).is_empty());
+        let events = fixture.run([QUIET, QUIET], secs(0.5));
+
+        assert_eq!(events.len(), 1, "{:?}", events);
+        let event = &events[0];
+        assert!(
+            event.time >= secs(1.) && event.time < secs(1.02),
+            "{:?}",
+            event
+        );
+        // Held for 200ms, then released within a few fast time constants
+        assert!(
+            event.duration > secs(0.2) && event.duration < secs(0.26),
+            "{:?}",
+            event
+        );
+        assert!((event.loudness_db - 20. * 0.5f32.log10()).abs() < 0.5);
+        let direction = event.direction.unwrap();
+        assert!((direction.azimuth + 30.).abs() < 1e-3, "{:?}", direction);
+        assert_eq!(fixture.detector.events().len(), 1);
+    }
+
+    #[test]
+    fn onsets_close_together_are_one_event() {
+        let mut fixture = Fixture::stereo();
+        fixture.run([QUIET, QUIET], secs(1.));
+        fixture.run([0.5, QUIET], secs(0.03));
+        fixture.run([0.5, 0.5], secs(0.2));
+        let events = fixture.run([QUIET, QUIET], secs(0.5));
+        assert_eq!(events.len(), 1, "{:?}", events);
+        // Between the two speakers
+        let direction = events[0].direction.unwrap();
+        assert!(direction.azimuth.abs() < 15., "{:?}", direction);
+
+        // Further apart they are separate sounds
+        fixture.run([QUIET, QUIET], secs(2.));
+        fixture.run([0.5, QUIET], secs(0.1));
+        fixture.run([0.5, 0.5], secs(0.2));
+        let events = fixture.run([QUIET, QUIET], secs(0.5));
+        assert_eq!(events.len(), 2, "{:?}", events);
+    }
+
+    #[test]
+    fn long_sounds_are_cut_off() {
+        let mut fixture = Fixture::stereo();
+        fixture.run([QUIET, QUIET], secs(1.));
+        let events = fixture.run([0.5, QUIET], secs(8.));
+        assert_eq!(events.len(), 1, "{:?}", events);
+        let duration = events[0].duration;
+        assert!(duration > MAX_DURATION && duration < MAX_DURATION + secs(0.02));
+    }
+
+    #[test]
+    fn quiet_steps_are_ignored() {
+        let mut fixture = Fixture::stereo();
+        fixture.run([QUIET, QUIET], secs(1.));
+        // 12dB up, but below the minimum level
+        fixture.run([QUIET * 4., QUIET], secs(0.2));
+        assert!(fixture.run([QUIET, QUIET], secs(0.5)).is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use eframe::epaint::Pos2;
+
+use crate::placement::SpeakerPlacement;
+use crate::source::{ChannelLayout, Speaker};
+
+pub static WINDOW_SIZE: f32 = 320.;
+pub static INNER_RADIUS_FACTOR: f32 = 0.4;
+/// Where the overhead ring, drawn inside the ear level one, meets it.
+pub static OVERHEAD_RADIUS_FACTOR: f32 = 0.65;
+pub static OUTER_RADIUS: f32 = WINDOW_SIZE / 2. - 20.;
+
+/// The point at `radius` (a fraction of `OUTER_RADIUS`) from the centre in
+/// the direction `azimuth`, in degrees clockwise from straight ahead.
+pub fn bearing_point(azimuth: f32, radius: f32) -> Pos2 {
+    let theta = (azimuth + 270.) * std::f32::consts::PI / 180.;
+    Pos2 {
+        x: theta.cos() * OUTER_RADIUS * radius + WINDOW_SIZE / 2.,
+        y: theta.sin() * OUTER_RADIUS * radius + WINDOW_SIZE / 2.,
+    }
+}
+
+/// The outline of the band between `inner` and `outer` (as fractions of
+/// `OUTER_RADIUS`) spanning `range` in screen degrees, clockwise from the
+/// right so that straight ahead is 270.
+pub fn arc_points(range: std::ops::Range<f32>, inner: f32, outer: f32) -> Vec<Pos2> {
+    let center = Pos2 {
+        x: WINDOW_SIZE / 2.,
+        y: WINDOW_SIZE / 2.,
+    };
+    // A point per degree or so, always including both ends
+    let steps = ((range.end - range.start).ceil() as usize).max(1);
+    let angles: Vec<f32> = (0..=steps)
+        .map(|step| {
+            let theta = range.start + (range.end - range.start) * step as f32 / steps as f32;
+            theta * std::f32::consts::PI / 180.
+        })
+        .collect();
+
+    // outer arc
+    let mut points: Vec<Pos2> = angles
+        .iter()
+        .map(|theta| Pos2 {
+            x: theta.cos() * OUTER_RADIUS * outer + center.x,
+            y: theta.sin() * OUTER_RADIUS * outer + center.y,
+        })
+        .collect();
+
+    // inner arc
+    let mut inner_points: Vec<Pos2> = angles
+        .iter()
+        .rev()
+        .map(|theta| Pos2 {
+            x: theta.cos() * OUTER_RADIUS * inner + center.x,
+            y: theta.sin() * OUTER_RADIUS * inner + center.y,
+        })
+        .collect();
+
+    points.append(&mut inner_points);
+
+    points
+}
+
+/// Speakers placed closer together than this, in degrees, share a sector.
+static COINCIDENT: f32 = 0.5;
+
+/// Sector ranges in screen degrees for speakers placed at the given azimuths
+/// (clockwise from straight ahead). Each sector reaches halfway to the
+/// neighbouring speaker on either side, so a lone speaker gets the full circle.
+/// Speakers at the same azimuth split their sector between them.
+fn sector_ranges(mut azimuths: Vec<(Speaker, f32)>) -> Vec<(Speaker, std::ops::Range<f32>)> {
+    for (_, azimuth) in &mut azimuths {
+        *azimuth = azimuth.rem_euclid(360.);
+    }
+    azimuths.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    // Speakers at the same place, each with its azimuth
+    let mut groups: Vec<Vec<(Speaker, f32)>> = Vec::new();
+    for (speaker, azimuth) in azimuths {
+        match groups.last_mut() {
+            Some(group) if azimuth - group[group.len() - 1].1 < COINCIDENT => {
+                group.push((speaker, azimuth))
+            }
+            _ => groups.push(vec![(speaker, azimuth)]),
+        }
+    }
+    // Either side of straight ahead
+    if groups.len() > 1 && groups[0][0].1 + 360. - groups[groups.len() - 1][0].1 < COINCIDENT {
+        let mut last = groups.pop().unwrap();
+        for (_, azimuth) in &mut last {
+            *azimuth -= 360.;
+        }
+        last.append(&mut groups[0]);
+        groups[0] = last;
+    }
+    let groups: Vec<(f32, Vec<Speaker>)> = groups
+        .into_iter()
+        .map(|group| {
+            let azimuth =
+                group.iter().map(|(_, azimuth)| azimuth).sum::<f32>() / group.len() as f32;
+            (
+                azimuth,
+                group.into_iter().map(|(speaker, _)| speaker).collect(),
+            )
+        })
+        .collect();
+
+    let count = groups.len();
+    (0..count)
+        .flat_map(|i| {
+            let (azimuth, speakers) = &groups[i];
+            let previous = groups[(i + count - 1) % count].0;
+            let next = groups[(i + 1) % count].0;
+            // Distances to the neighbours going round
+            let (before, after) = if count == 1 {
+                (360., 360.)
+            } else {
+                (
+                    (azimuth - previous).rem_euclid(360.),
+                    (next - azimuth).rem_euclid(360.),
+                )
+            };
+            let start = azimuth - before / 2. + 270.;
+            let width = (before + after) / 2. / speakers.len() as f32;
+            speakers.iter().enumerate().map(move |(j, speaker)| {
+                let from = start + width * j as f32;
+                (*speaker, from..from + width)
+            })
+        })
+        .collect()
+}
+
+/// The outline of the radar sector drawn for each directional speaker of
+/// `layout`, placed according to `placement`. Height speakers get a ring of
+/// their own inside the ear level one, so that sounds above the listener sit
+/// nearer the centre.
+pub fn sectors(layout: &ChannelLayout, placement: &SpeakerPlacement) -> Vec<(Speaker, Vec<Pos2>)> {
+    let (overhead, ear_level): (Vec<_>, Vec<_>) = layout
+        .speakers()
+        .iter()
+        .filter_map(|speaker| Some((*speaker, placement.azimuth(*speaker)?)))
+        .partition(|(speaker, _)| speaker.is_overhead());
+    let ear_level_inner = if overhead.is_empty() {
+        INNER_RADIUS_FACTOR
+    } else {
+        OVERHEAD_RADIUS_FACTOR
+    };
+
+    let ear_level = sector_ranges(ear_level)
+        .into_iter()
+        .map(|(speaker, range)| (speaker, arc_points(range, ear_level_inner, 1.)));
+    let overhead = sector_ranges(overhead).into_iter().map(|(speaker, range)| {
+        let points = arc_points(range, INNER_RADIUS_FACTOR, OVERHEAD_RADIUS_FACTOR);
+        (speaker, points)
+    });
+    ear_level.chain(overhead).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Speaker::*;
+
+    /// The sector of `speaker` as its start in screen degrees, in 0..360, and
+    /// its width.
+    fn sector(ranges: &[(Speaker, std::ops::Range<f32>)], speaker: Speaker) -> (f32, f32) {
+        let (_, range) = ranges.iter().find(|(s, _)| *s == speaker).unwrap();
+        (range.start.rem_euclid(360.), range.end - range.start)
+    }
+
+    /// The sector spanning azimuths `from` to `to` clockwise, as `sector`
+    /// gives it.
+    fn spanning(from: f32, to: f32) -> (f32, f32) {
+        ((from + 270.).rem_euclid(360.), (to - from).rem_euclid(360.))
+    }
+
+    fn nominal(speakers: &[Speaker]) -> Vec<(Speaker, std::ops::Range<f32>)> {
+        sector_ranges(
+            speakers
+                .iter()
+                .map(|speaker| (*speaker, speaker.azimuth().unwrap()))
+                .collect(),
+        )
+    }
+
+    fn assert_covers_circle(ranges: &[(Speaker, std::ops::Range<f32>)]) {
+        let total: f32 = ranges
+            .iter()
+            .map(|(_, range)| range.end - range.start)
+            .sum();
+        assert!((total - 360.).abs() < 1e-3, "{:?}", ranges);
+    }
+
+    #[test]
+    fn stereo_splits_front_and_back() {
+        let ranges = nominal(&[FrontLeft, FrontRight]);
+        assert_eq!(sector(&ranges, FrontRight), spanning(0., 180.));
+        assert_eq!(sector(&ranges, FrontLeft), spanning(180., 360.));
+    }
+
+    #[test]
+    fn five_one_boundaries_are_midpoints() {
+        let ranges = nominal(&[FrontLeft, FrontRight, FrontCenter, BackLeft, BackRight]);
+        assert_eq!(sector(&ranges, FrontCenter), spanning(-15., 15.));
+        assert_eq!(sector(&ranges, FrontRight), spanning(15., 90.));
+        assert_eq!(sector(&ranges, BackRight), spanning(90., 180.));
+        assert_eq!(sector(&ranges, BackLeft), spanning(180., 270.));
+        assert_eq!(sector(&ranges, FrontLeft), spanning(-90., -15.));
+        assert_covers_circle(&ranges);
+    }
+
+    #[test]
+    fn seven_one_boundaries_are_midpoints() {
+        let layout = ChannelLayout::surround_7_1();
+        let directional: Vec<Speaker> = layout
+            .speakers()
+            .iter()
+            .copied()
+            .filter(|speaker| speaker.azimuth().is_some())
+            .collect();
+        let ranges = nominal(&directional);
+        assert_eq!(sector(&ranges, SideRight), spanning(60., 120.));
+        assert_eq!(sector(&ranges, BackRight), spanning(120., 180.));
+        assert_eq!(sector(&ranges, BackLeft), spanning(180., 240.));
+        assert_eq!(sector(&ranges, SideLeft), spanning(-120., -60.));
+        assert_covers_circle(&ranges);
+    }
+
+    #[test]
+    fn sector_wraps_round_behind() {
+        let ranges = nominal(&[
+            FrontLeft,
+            FrontRight,
+            FrontCenter,
+            BackCenter,
+            SideLeft,
+            SideRight,
+        ]);
+        assert_eq!(sector(&ranges, BackCenter), spanning(135., 225.));
+        // -180 and 180 are the same place
+        let ranges = sector_ranges(vec![(FrontCenter, 0.), (BackCenter, -180.)]);
+        assert_eq!(sector(&ranges, BackCenter), spanning(90., 270.));
+    }
+
+    #[test]
+    fn lone_speaker_gets_the_circle() {
+        let ranges = nominal(&[FrontCenter]);
+        // From straight behind, round through straight ahead
+        assert_eq!(sector(&ranges, FrontCenter), (90., 360.));
+    }
+
+    #[test]
+    fn coincident_speakers_share_a_sector() {
+        let ranges = sector_ranges(vec![(FrontLeft, 0.), (FrontRight, 0.)]);
+        assert_eq!(sector(&ranges, FrontLeft).1, 180.);
+        assert_eq!(sector(&ranges, FrontRight).1, 180.);
+        assert_covers_circle(&ranges);
+
+        let ranges = sector_ranges(vec![
+            (FrontLeft, -30.),
+            (FrontCenter, 30.),
+            (FrontRight, 30.),
+            (BackCenter, 180.),
+        ]);
+        assert_eq!(sector(&ranges, FrontCenter), spanning(0., 52.5));
+        assert_eq!(sector(&ranges, FrontRight), spanning(52.5, 105.));
+        assert_covers_circle(&ranges);
+
+        // Either side of straight ahead
+        let ranges = sector_ranges(vec![
+            (FrontLeft, -0.1),
+            (FrontRight, 0.1),
+            (BackCenter, 180.),
+        ]);
+        assert_eq!(sector(&ranges, FrontLeft).1, 90.);
+        assert_eq!(sector(&ranges, FrontRight).1, 90.);
+        assert_eq!(sector(&ranges, BackCenter), spanning(90., 270.));
+    }
+}
@@ -1,290 +1,210 @@
 #![windows_subsystem = "windows"]
-use anyhow::Result;
-use image::GenericImageView;
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, ValueEnum};
 use log::*;
-
-use windows::{
-    core::*, Win32::Media::Audio::Endpoints::IAudioMeterInformation, Win32::Media::Audio::*,
-    Win32::System::Com::*, Win32::UI::WindowsAndMessaging::*,
-};
-
-use eframe::{
-    egui,
-    epaint::{CircleShape, Color32, PathShape, Pos2, Stroke},
-};
-
-static FRONT_LEFT: usize = 0;
-static FRONT_RIGHT: usize = 1;
-static FRONT: usize = 2;
-static REAR_LEFT: usize = 4;
-static REAR_RIGHT: usize = 5;
-static LEFT: usize = 6;
-static RIGHT: usize = 7;
-
-static WINDOW_SIZE: f32 = 320.;
-static INNER_RADIUS_FACTOR: f32 = 0.4;
-static OUTER_RADIUS: f32 = WINDOW_SIZE / 2. - 20.;
-
-fn arc_points(range: std::ops::Range<i32>) -> Vec<Pos2> {
-    let center = Pos2 {
-        x: WINDOW_SIZE / 2.,
-        y: WINDOW_SIZE / 2.,
-    };
-    // outer arc
-    let mut points: Vec<Pos2> = range
-        .clone()
-        .map(|theta| Pos2 {
-            x: (theta as f32 * std::f32::consts::PI / 180.).cos() * OUTER_RADIUS + center.x,
-            y: (theta as f32 * std::f32::consts::PI / 180.).sin() * OUTER_RADIUS + center.y,
-        })
-        .collect();
-
-    // inner arc
-    let mut inner_points: Vec<Pos2> = range
-        .rev()
-        .map(|theta| Pos2 {
-            x: (theta as f32 * std::f32::consts::PI / 180.).cos()
-                * OUTER_RADIUS
-                * INNER_RADIUS_FACTOR
-                + center.x,
-            y: (theta as f32 * std::f32::consts::PI / 180.).sin()
-                * OUTER_RADIUS
-                * INNER_RADIUS_FACTOR
-                + center.y,
-        })
-        .collect();
-
-    points.append(&mut inner_points);
-
-    points
+use std::path::PathBuf;
+
+mod app;
+mod ballistics;
+mod classify;
+mod direction;
+mod distance;
+mod dsp;
+mod events;
+mod geometry;
+mod mapping;
+#[cfg(feature = "onnx")]
+mod model;
+mod noise;
+mod placement;
+mod radar;
+mod settings;
+mod source;
+mod spectrum;
+mod templates;
+mod tracking;
+
+use classify::SoundModel;
+use source::{stdin::SampleFormat, synth::Scene, ChannelLayout, LevelSource, NamedLayout};
+use templates::TemplateMatcher;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum SourceKind {
+    /// Peak meter of the default Windows render endpoint
+    Wasapi,
+    /// Loopback capture of the default Windows render endpoint, needed for
+    /// frequency filtering; falls back to wasapi if it can't be opened
+    Loopback,
+    /// Monitor of a PulseAudio or PipeWire sink
+    Pulse,
+    /// An ALSA capture PCM, e.g. an snd-aloop loopback or dsnoop device
+    Alsa,
+    /// A WAV, FLAC or Ogg recording, played back in real time
+    File,
+    /// Interleaved raw PCM piped to stdin
+    Stdin,
+    /// A generated test scene
+    Synth,
 }
 
-fn get_audio_interface() -> Result<IAudioMeterInformation> {
-    unsafe {
-        info!("Initializing COM");
-        let res = CoInitialize(None);
-        if res.is_err() {
-            let error = format!("Failed to init '{:?}'", res);
-            MessageBoxA(
-                None,
-                Some(PCSTR::from_raw(error.as_ptr())),
-                s!("Error"),
-                MB_OK,
-            );
-        }
-
-        info!("Creating instance");
-        let enumerator: IMMDeviceEnumerator =
-            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
-
-        info!("Getting default endpoint");
-        let endpoint = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
-        info!("Getting endpoint id");
-
-        let meter: IAudioMeterInformation = endpoint.Activate(CLSCTX_ALL, None)?;
-
-        info!("Got audio meter");
-
-        let channel_count = meter.GetMeteringChannelCount()?;
-        if channel_count != 8 {
-            let error = format!(
-                "Expected 8 channels for 7.1 audio, found only {}",
-                channel_count
-            );
-            MessageBoxA(
-                None,
-                Some(PCSTR::from_raw(error.as_ptr())),
-                s!("Error"),
-                MB_OK,
-            );
-            std::process::exit(1);
+impl Default for SourceKind {
+    /// The platform's own capture, if it was built in, or else the test scene
+    /// so that a plain build still shows something.
+    fn default() -> Self {
+        if cfg!(windows) {
+            SourceKind::Loopback
+        } else if cfg!(feature = "pulse") {
+            SourceKind::Pulse
+        } else if cfg!(feature = "alsa") {
+            SourceKind::Alsa
+        } else {
+            SourceKind::Synth
         }
-
-        Ok(meter)
     }
 }
 
-fn get_icon_data() -> Option<eframe::IconData> {
-    let bytes = include_bytes!("../icon/panopticon.png");
-
-    let image = image::load_from_memory_with_format(bytes, image::ImageFormat::Png)
-        .expect("Embedded icon must be a valid PNG");
-
-    Some(eframe::IconData {
-        width: image.dimensions().0,
-        height: image.dimensions().1,
-        rgba: image.into_bytes(),
-    })
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Where to read channel levels from
+    #[arg(long, value_enum, default_value_t)]
+    source: SourceKind,
+
+    /// Device to read from; for pulse, the sink whose monitor is captured
+    #[arg(long)]
+    device: Option<String>,
+
+    /// Recording to play, for file
+    #[arg(long)]
+    file: Option<PathBuf>,
+
+    /// Number of channels to capture, for pulse, alsa, stdin and synth, in
+    /// the default layout for that many; the sink's own for pulse, or else 8,
+    /// if neither this nor --layout is given
+    #[arg(long)]
+    channels: Option<usize>,
+
+    /// Speaker layout to capture, for pulse, alsa, stdin and synth; overrides
+    /// the sink's for pulse and the device's for alsa
+    #[arg(long, value_enum)]
+    layout: Option<NamedLayout>,
+
+    /// Sample format of raw PCM, for stdin
+    #[arg(long, value_enum, default_value_t = SampleFormat::F32le)]
+    format: SampleFormat,
+
+    /// Sample rate of raw PCM, for stdin
+    #[arg(long, default_value_t = 48000)]
+    rate: u32,
+
+    /// Test scene to generate, for synth
+    #[arg(long, value_enum, default_value_t = Scene::Orbit)]
+    scene: Scene,
+
+    /// How fast the scene moves in degrees per second, for synth
+    #[arg(long, default_value_t = 45.)]
+    speed: f32,
+
+    /// Level of the scene in dBFS, for synth
+    #[arg(long, default_value_t = -6., allow_negative_numbers = true)]
+    level: f32,
+
+    /// ONNX model to classify sound events with instead of the heuristics
+    #[arg(long)]
+    model: Option<PathBuf>,
+
+    /// Class names of the model's outputs, one per line
+    #[arg(long)]
+    labels: Option<PathBuf>,
+
+    /// Sample rate the model expects audio at
+    #[arg(long, default_value_t = 16000)]
+    model_rate: u32,
+
+    /// Recording of a specific sound to recognise, named after the file;
+    /// may be given more than once
+    #[arg(long)]
+    template: Vec<PathBuf>,
 }
 
-fn run_ui(meter: IAudioMeterInformation) {
-    let icon_data = get_icon_data();
-
-    let front_points = arc_points(250..291);
-    let front_right_points = arc_points(290..341);
-    let right_points = arc_points(340..391);
-    let rear_right_points = arc_points(30..91);
-    let rear_left_points = arc_points(90..151);
-    let left_points = arc_points(150..201);
-    let front_left_points = arc_points(200..251);
-
-    let options = eframe::NativeOptions {
-        initial_window_size: Some(egui::vec2(WINDOW_SIZE, WINDOW_SIZE)),
-        icon_data,
-        ..Default::default()
-    };
-    eframe::run_native(
-        "Panopticon",
-        options,
-        Box::new(|_cc| {
-            Box::new(PanApp {
-                front_points,
-                front_right_points,
-                right_points,
-                rear_right_points,
-                rear_left_points,
-                left_points,
-                front_left_points,
-                meter,
-            })
-        }),
-    );
-}
-
-struct PanApp {
-    front_points: Vec<Pos2>,
-    front_right_points: Vec<Pos2>,
-    right_points: Vec<Pos2>,
-    rear_right_points: Vec<Pos2>,
-    rear_left_points: Vec<Pos2>,
-    left_points: Vec<Pos2>,
-    front_left_points: Vec<Pos2>,
-    meter: IAudioMeterInformation,
+/// The layout asked for with `--layout` or `--channels`, if either was given.
+fn requested_layout(args: &Args) -> Result<Option<ChannelLayout>> {
+    match (args.layout, args.channels) {
+        (Some(name), Some(channels)) if name.layout().len() != channels => bail!(
+            "--layout gives {} channels but --channels gives {}",
+            name.layout().len(),
+            channels
+        ),
+        (Some(name), _) => Ok(Some(name.layout())),
+        (None, Some(channels)) => ChannelLayout::default_for(channels)
+            .map(Some)
+            .ok_or_else(|| anyhow!("No default speaker layout for {} channels", channels)),
+        (None, None) => Ok(None),
+    }
 }
 
-impl eframe::App for PanApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        static mut PEAK_VALUES: [f32; 8] = [0.; 8];
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let painter = ui.painter();
-
-            unsafe {
-                self.meter.GetChannelsPeakValues(&mut PEAK_VALUES).unwrap();
-
-                for (shape, meter) in [
-                    (&self.front_points, PEAK_VALUES[FRONT]),
-                    (&self.front_right_points, PEAK_VALUES[FRONT_RIGHT]),
-                    (&self.right_points, PEAK_VALUES[RIGHT]),
-                    (&self.rear_right_points, PEAK_VALUES[REAR_RIGHT]),
-                    (&self.rear_left_points, PEAK_VALUES[REAR_LEFT]),
-                    (&self.left_points, PEAK_VALUES[LEFT]),
-                    (&self.front_left_points, PEAK_VALUES[FRONT_LEFT]),
-                ] {
-                    painter.add(PathShape {
-                        points: shape.clone(),
-                        closed: true,
-                        fill: Color32::from_rgba_premultiplied((meter * 255.) as u8, 0, 0, 255),
-                        stroke: Stroke {
-                            width: 1.,
-                            color: Color32::BLACK,
-                        },
-                    });
-                }
-            }
-
-            //Concentric rings
-            for factor in [1., 0.8, 0.6, 0.4, 0.2] {
-                painter.add(CircleShape {
-                    radius: OUTER_RADIUS * INNER_RADIUS_FACTOR * factor,
-                    fill: Color32::BLACK,
-                    stroke: Stroke {
-                        width: 1.,
-                        color: Color32::GREEN,
-                    },
-                    center: Pos2 {
-                        x: WINDOW_SIZE / 2.,
-                        y: WINDOW_SIZE / 2.,
-                    },
-                });
+fn open_source(args: &Args) -> Result<Box<dyn LevelSource>> {
+    match args.source {
+        #[cfg(windows)]
+        SourceKind::Wasapi => Ok(Box::new(source::wasapi::WasapiSource::default_endpoint()?)),
+        #[cfg(windows)]
+        SourceKind::Loopback => match source::wasapi::loopback() {
+            Ok(source) => Ok(Box::new(source)),
+            // This is the default, and with no console an error would go unseen
+            Err(e) => {
+                warn!("Falling back to the peak meter: {:?}", e);
+                Ok(Box::new(source::wasapi::WasapiSource::default_endpoint()?))
             }
+        },
+        #[cfg(feature = "pulse")]
+        SourceKind::Pulse => Ok(Box::new(source::pulse::monitor(
+            args.device.as_deref(),
+            requested_layout(args)?,
+        )?)),
+        #[cfg(feature = "alsa")]
+        SourceKind::Alsa => {
+            // Without --layout, the device's own channel map is used
+            let named = requested_layout(args)?.filter(|_| args.layout.is_some());
+            let channels = named
+                .as_ref()
+                .map_or(args.channels.unwrap_or(8), ChannelLayout::len);
+            Ok(Box::new(source::alsa::capture(
+                args.device.as_deref().unwrap_or("default"),
+                channels,
+                named,
+            )?))
+        }
+        SourceKind::File => match &args.file {
+            Some(path) => Ok(Box::new(source::file::FileSource::open(path)?)),
+            None => bail!("--file is required to play a recording"),
+        },
+        SourceKind::Stdin => Ok(Box::new(source::stdin::capture(
+            args.format,
+            args.rate,
+            requested_layout(args)?.unwrap_or_else(ChannelLayout::surround_7_1),
+        )?)),
+        SourceKind::Synth => Ok(Box::new(source::synth::generate(
+            args.scene,
+            requested_layout(args)?.unwrap_or_else(ChannelLayout::surround_7_1),
+            args.speed,
+            args.level,
+        )?)),
+        #[allow(unreachable_patterns)]
+        kind => bail!("Panopticon was built without {:?} support", kind),
+    }
+}
 
-            // Horitontal Radar axis
-            painter.add(PathShape {
-                points: vec![
-                    Pos2 {
-                        x: WINDOW_SIZE / 2. - (OUTER_RADIUS * INNER_RADIUS_FACTOR),
-                        y: WINDOW_SIZE / 2.,
-                    },
-                    Pos2 {
-                        x: WINDOW_SIZE / 2. + (OUTER_RADIUS * INNER_RADIUS_FACTOR),
-                        y: WINDOW_SIZE / 2.,
-                    },
-                ],
-                stroke: Stroke {
-                    width: 1.,
-                    color: Color32::GREEN,
-                },
-                closed: false,
-                fill: Color32::TRANSPARENT,
-            });
-
-            // Verical Radar axis
-            painter.add(PathShape {
-                points: vec![
-                    Pos2 {
-                        x: WINDOW_SIZE / 2.,
-                        y: WINDOW_SIZE / 2. - (OUTER_RADIUS * INNER_RADIUS_FACTOR),
-                    },
-                    Pos2 {
-                        x: WINDOW_SIZE / 2.,
-                        y: WINDOW_SIZE / 2. + (OUTER_RADIUS * INNER_RADIUS_FACTOR),
-                    },
-                ],
-                stroke: Stroke {
-                    width: 1.,
-                    color: Color32::GREEN,
-                },
-                closed: false,
-                fill: Color32::TRANSPARENT,
-            });
-
-            // draw radar sweep
-            let angle = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis()
-                / 10
-                % 360;
-
-            // Sweeping hand
-            let sweep = Pos2 {
-                x: (angle as f32 * std::f32::consts::PI / 180.).cos()
-                    * OUTER_RADIUS
-                    * INNER_RADIUS_FACTOR
-                    + WINDOW_SIZE / 2.,
-                y: (angle as f32 * std::f32::consts::PI / 180.).sin()
-                    * OUTER_RADIUS
-                    * INNER_RADIUS_FACTOR
-                    + WINDOW_SIZE / 2.,
-            };
-            painter.add(PathShape {
-                points: vec![
-                    Pos2 {
-                        x: WINDOW_SIZE / 2.,
-                        y: WINDOW_SIZE / 2.,
-                    },
-                    sweep,
-                ],
-                stroke: Stroke {
-                    width: 2.,
-                    color: Color32::LIGHT_GREEN,
-                },
-                closed: false,
-                fill: Color32::TRANSPARENT,
-            });
-        });
-
-        ctx.request_repaint_after(std::time::Duration::from_millis(33));
+fn load_model(args: &Args) -> Result<Option<Box<dyn SoundModel>>> {
+    match &args.model {
+        #[cfg(feature = "onnx")]
+        Some(path) => Ok(Some(Box::new(model::OnnxModel::load(
+            path,
+            args.labels.as_deref(),
+            args.model_rate,
+        )?))),
+        #[cfg(not(feature = "onnx"))]
+        Some(_) => bail!("Panopticon was built without ONNX model support"),
+        None => Ok(None),
     }
 }
 
@@ -293,11 +213,20 @@ fn main() {
         .filter_level(log::LevelFilter::Info)
         .init();
 
-    match get_audio_interface() {
+    let args = Args::parse();
+
+    let loaded = open_source(&args).and_then(|source| {
+        Ok((
+            source,
+            load_model(&args)?,
+            TemplateMatcher::load(&args.template)?,
+        ))
+    });
+    match loaded {
         Err(e) => {
             error!("{:?}", e);
             std::process::exit(1);
         }
-        Ok(meter) => run_ui(meter),
+        Ok((source, model, templates)) => app::run_ui(source, model, templates),
     }
 }
@@ -0,0 +1,204 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::source::{ChannelLayout, Speaker};
+
+/// Gain applied to one input channel before it reaches the radar.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelTrim {
+    pub gain_db: f32,
+    pub mute: bool,
+    /// Flip polarity. Levels are magnitudes, so this only matters where
+    /// channels are mixed together, as when classifying sound events.
+    pub invert: bool,
+}
+
+impl Default for ChannelTrim {
+    fn default() -> Self {
+        Self {
+            gain_db: 0.,
+            mute: false,
+            invert: false,
+        }
+    }
+}
+
+impl ChannelTrim {
+    /// The linear factor applied to samples of the channel.
+    pub fn factor(&self) -> f32 {
+        if self.mute {
+            0.
+        } else {
+            let gain = 10f32.powf(self.gain_db / 20.);
+            if self.invert {
+                -gain
+            } else {
+                gain
+            }
+        }
+    }
+}
+
+/// Which input channel feeds each radar sector, and per channel trims, for
+/// drivers that swap channel pairs or run some channels hot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChannelMap {
+    /// Input channel feeding a speaker's sector, where it differs from the layout.
+    pub routes: BTreeMap<Speaker, usize>,
+    /// Trims by input channel; channels past the end are untrimmed.
+    pub trims: Vec<ChannelTrim>,
+}
+
+impl ChannelMap {
+    /// The input channel whose level is shown for `speaker`.
+    pub fn channel(&self, layout: &ChannelLayout, speaker: Speaker) -> Option<usize> {
+        match self.routes.get(&speaker) {
+            Some(channel) if *channel < layout.len() => Some(*channel),
+            _ => layout.position(speaker),
+        }
+    }
+
+    /// Apply the trims, polarity included, to interleaved `samples` of
+    /// `channels` channels.
+    pub fn apply(&self, samples: &mut [f32], channels: usize) {
+        let factors: Vec<f32> = (0..channels)
+            .map(|channel| self.trim(channel).factor())
+            .collect();
+        for frame in samples.chunks_exact_mut(channels) {
+            for (sample, factor) in frame.iter_mut().zip(&factors) {
+                *sample *= factor;
+            }
+        }
+    }
+
+    /// The same routes without trims, for levels that are already trimmed.
+    pub fn untrimmed(&self) -> Self {
+        Self {
+            routes: self.routes.clone(),
+            trims: Vec::new(),
+        }
+    }
+
+    pub fn trim(&self, channel: usize) -> ChannelTrim {
+        self.trims.get(channel).cloned().unwrap_or_default()
+    }
+
+    /// The trimmed level of `speaker`'s sector given per channel `levels`.
+    pub fn level(&self, layout: &ChannelLayout, levels: &[f32], speaker: Speaker) -> f32 {
+        self.channel(layout, speaker).map_or(0., |channel| {
+            (levels[channel] * self.trim(channel).factor())
+                .abs()
+                .min(1.)
+        })
+    }
+
+    /// Route each side speaker from the matching rear channel and vice versa.
+    fn swap_side_and_rear(&mut self, layout: &ChannelLayout) {
+        use Speaker::*;
+        for (a, b) in [(SideLeft, BackLeft), (SideRight, BackRight)] {
+            if let (Some(to_a), Some(to_b)) = (self.channel(layout, b), self.channel(layout, a)) {
+                self.routes.insert(a, to_a);
+                self.routes.insert(b, to_b);
+            }
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, layout: &ChannelLayout, speakers: &[Speaker]) {
+        let name = |channel: usize| format!("{} ({:?})", channel + 1, layout.speakers()[channel]);
+
+        egui::Grid::new("routes").show(ui, |ui| {
+            for speaker in speakers {
+                ui.label(format!("{:?}", speaker));
+                let mut channel = self.channel(layout, *speaker);
+                egui::ComboBox::from_id_source(speaker)
+                    .selected_text(channel.map_or("None".to_string(), name))
+                    .show_ui(ui, |ui| {
+                        for input in 0..layout.len() {
+                            ui.selectable_value(&mut channel, Some(input), name(input));
+                        }
+                    });
+                if let Some(channel) = channel {
+                    if Some(channel) == layout.position(*speaker) {
+                        self.routes.remove(speaker);
+                    } else {
+                        self.routes.insert(*speaker, channel);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        if self.trims.len() < layout.len() {
+            self.trims.resize(layout.len(), ChannelTrim::default());
+        }
+        egui::Grid::new("trims").show(ui, |ui| {
+            for (channel, trim) in self.trims.iter_mut().take(layout.len()).enumerate() {
+                ui.label(name(channel));
+                ui.add(
+                    egui::DragValue::new(&mut trim.gain_db)
+                        .speed(0.1)
+                        .clamp_range(-24.0..=24.0)
+                        .suffix(" dB"),
+                );
+                ui.checkbox(&mut trim.mute, "Mute");
+                ui.checkbox(&mut trim.invert, "Invert");
+                ui.end_row();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Swap side/rear").clicked() {
+                self.swap_side_and_rear(layout);
+            }
+            if ui.button("Reset").clicked() {
+                *self = Self::default();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverted_channel_cancels_in_a_mix() {
+        let map = ChannelMap {
+            trims: vec![
+                ChannelTrim::default(),
+                ChannelTrim {
+                    invert: true,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut samples = vec![0.5, 0.5, -0.25, -0.25];
+        map.apply(&mut samples, 2);
+        assert_eq!(samples, [0.5, -0.5, -0.25, 0.25]);
+        assert!(samples.chunks(2).all(|frame| frame[0] + frame[1] == 0.));
+    }
+
+    #[test]
+    fn muted_and_trimmed_channels() {
+        let map = ChannelMap {
+            trims: vec![
+                ChannelTrim {
+                    mute: true,
+                    ..Default::default()
+                },
+                ChannelTrim {
+                    gain_db: -6.0206,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut samples = vec![1., 1.];
+        map.apply(&mut samples, 2);
+        assert_eq!(samples[0], 0.);
+        assert!((samples[1] - 0.5).abs() < 1e-4);
+    }
+}
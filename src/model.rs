@@ -0,0 +1,158 @@
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use std::path::Path;
+use tract_onnx::prelude::*;
+use tract_onnx::tract_hir::infer::Factoid;
+
+use crate::classify::SoundModel;
+
+type Plan = SimplePlan<TypedFact, Box<dyn TypedOp>, Graph<TypedFact, Box<dyn TypedOp>>>;
+
+/// An ONNX audio classifier, run on the CPU by tract.
+///
+/// The model takes one fixed-size window of audio starting at a sound's
+/// onset, shaped `[1, samples]` for a mono mix or `[1, channels, samples]`,
+/// and returns one score per class.
+pub struct OnnxModel {
+    plan: Plan,
+    /// Channels the model takes, or `None` for a `[1, samples]` mono input.
+    channels: Option<usize>,
+    window: usize,
+    sample_rate: u32,
+    labels: Vec<String>,
+}
+
+impl OnnxModel {
+    /// Load the model at `path` that expects audio at `sample_rate`, with
+    /// class names one per line in `labels`.
+    pub fn load(path: &Path, labels: Option<&Path>, sample_rate: u32) -> Result<Self> {
+        info!("Loading sound model {}", path.display());
+        let model = tract_onnx::onnx().model_for_path(path)?;
+        // Models are often exported with a symbolic batch dimension; only one
+        // clip is classified at a time, so pin it to 1.
+        let input = &model.input_fact(0)?.shape;
+        let shape: TVec<usize> = input
+            .dims()
+            .enumerate()
+            .map(|(axis, dim)| {
+                let dim = dim
+                    .concretize()
+                    .and_then(|dim| usize::try_from(dim.as_i64()?).ok());
+                dim.or((axis == 0).then_some(1))
+            })
+            .collect::<Option<_>>()
+            .filter(|_| !input.is_open())
+            .ok_or_else(|| anyhow!("The model's input must have a fixed shape"))?;
+        let model = model.with_input_fact(0, f32::fact(&shape).into())?;
+        let (channels, window) = match *shape {
+            [1, window] => (None, window),
+            [1, channels, window] => (Some(channels), window),
+            _ => bail!(
+                "The model's input must be [1, samples] or [1, channels, samples], not {:?}",
+                shape
+            ),
+        };
+        let plan = model.into_optimized()?.into_runnable()?;
+
+        let labels = match labels {
+            Some(labels) => std::fs::read_to_string(labels)
+                .map_err(|e| anyhow!("Failed to read {}: {}", labels.display(), e))?
+                .lines()
+                .map(str::trim)
+                .filter(|label| !label.is_empty())
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        };
+        info!(
+            "Sound model takes {} samples of {} at {}Hz",
+            window,
+            match channels {
+                Some(channels) => format!("{} channels", channels),
+                None => "mono".to_string(),
+            },
+            sample_rate
+        );
+
+        Ok(Self {
+            plan,
+            channels,
+            window,
+            sample_rate,
+            labels,
+        })
+    }
+}
+
+/// Linearly interpolate `samples` at `from`Hz to `length` samples at `to`Hz,
+/// padding with silence.
+fn resample(samples: &[f32], from: u32, to: u32, length: usize) -> Vec<f32> {
+    let step = from as f32 / to as f32;
+    (0..length)
+        .map(|i| {
+            let position = i as f32 * step;
+            let index = position as usize;
+            let fraction = position - index as f32;
+            match (samples.get(index), samples.get(index + 1)) {
+                (Some(a), Some(b)) => a + (b - a) * fraction,
+                (Some(a), None) => *a,
+                _ => 0.,
+            }
+        })
+        .collect()
+}
+
+impl SoundModel for OnnxModel {
+    fn predict(
+        &mut self,
+        clip: &[f32],
+        channels: usize,
+        sample_rate: u32,
+    ) -> Result<(String, f32)> {
+        let split = |channel: usize| -> Vec<f32> {
+            clip.iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect()
+        };
+        let inputs: Vec<Vec<f32>> = match self.channels {
+            Some(model_channels) if model_channels == channels => {
+                (0..channels).map(split).collect()
+            }
+            None | Some(1) => vec![clip
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                .collect()],
+            Some(model_channels) => bail!(
+                "The model takes {} channels but the source has {}",
+                model_channels,
+                channels
+            ),
+        };
+
+        let data: Vec<f32> = inputs
+            .iter()
+            .flat_map(|input| resample(input, sample_rate, self.sample_rate, self.window))
+            .collect();
+        let shape: &[usize] = match self.channels {
+            Some(channels) => &[1, channels, self.window],
+            None => &[1, self.window],
+        };
+        let input = Tensor::from_shape(shape, &data)?;
+        let outputs = self.plan.run(tvec!(input.into_tvalue()))?;
+        let scores = outputs[0].as_slice::<f32>()?;
+
+        let (class, score) = scores
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .ok_or_else(|| anyhow!("The model returned no scores"))?;
+        let label = self
+            .labels
+            .get(class)
+            .cloned()
+            .unwrap_or_else(|| format!("class {}", class));
+        Ok((label, *score))
+    }
+}
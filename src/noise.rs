@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+
+use crate::settings::NoiseSettings;
+
+/// Sub-windows the adaptation time is split into; the floor can rise once
+/// every sub-window rather than only once per adaptation time.
+static SUBWINDOWS: usize = 8;
+/// Time constant smoothing the power before its minimum is taken, in seconds.
+static SMOOTHING: f32 = 0.1;
+
+/// Per channel estimate of the steady background level, by minimum
+/// statistics: the lowest smoothed power over the adaptation time. This
+/// follows rain or engines as they change, but not sounds shorter than the
+/// adaptation time.
+pub struct NoiseFloor {
+    /// Smoothed power of each channel, once there has been any.
+    smoothed: Option<Vec<f32>>,
+    /// Minimum of each finished sub-window, by channel.
+    minima: Vec<VecDeque<f32>>,
+    /// Minimum so far of the current sub-window, by channel.
+    current: Vec<f32>,
+    /// Seconds into the current sub-window.
+    elapsed: f32,
+}
+
+impl NoiseFloor {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            smoothed: None,
+            minima: vec![VecDeque::new(); channels],
+            current: vec![f32::INFINITY; channels],
+            elapsed: 0.,
+        }
+    }
+
+    /// Advance the estimate by `elapsed` seconds given `levels`, which are
+    /// replaced by how far each rises above its background.
+    pub fn update(&mut self, levels: &mut [f32], elapsed: f32, settings: &NoiseSettings) {
+        let smoothed = self
+            .smoothed
+            .get_or_insert_with(|| levels.iter().map(|level| level * level).collect());
+        let smoothing = 1. - (-elapsed / SMOOTHING).exp();
+        let margin = 10f32.powf(settings.margin_db / 10.);
+
+        self.elapsed += elapsed;
+        let next_window = self.elapsed >= settings.adaptation / SUBWINDOWS as f32;
+        if next_window {
+            self.elapsed = 0.;
+        }
+
+        for (((level, smoothed), minima), current) in levels
+            .iter_mut()
+            .zip(smoothed.iter_mut())
+            .zip(&mut self.minima)
+            .zip(&mut self.current)
+        {
+            let power = *level * *level;
+            *smoothed += (power - *smoothed) * smoothing;
+            *current = current.min(*smoothed);
+
+            let floor = minima
+                .iter()
+                .fold(*current, |floor, minimum| floor.min(*minimum));
+            *level = (power - floor * margin).max(0.).sqrt();
+
+            if next_window {
+                if minima.len() == SUBWINDOWS {
+                    minima.pop_front();
+                }
+                minima.push_back(*current);
+                *current = *smoothed;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames fed to the floor, 10ms each.
+    static FRAME: f32 = 0.01;
+
+    #[test]
+    fn steady_background_is_removed() {
+        let settings = NoiseSettings::default();
+        let mut floor = NoiseFloor::new(1);
+        for _ in 0..200 {
+            let mut levels = [0.1];
+            floor.update(&mut levels, FRAME, &settings);
+            assert_eq!(levels[0], 0.);
+        }
+    }
+
+    #[test]
+    fn sounds_above_the_background_pass() {
+        let settings = NoiseSettings::default();
+        let mut floor = NoiseFloor::new(1);
+        for _ in 0..200 {
+            floor.update(&mut [0.1], FRAME, &settings);
+        }
+        let mut levels = [0.5];
+        floor.update(&mut levels, FRAME, &settings);
+        let margin = 10f32.powf(settings.margin_db / 10.);
+        let expected = (0.25 - 0.01 * margin).sqrt();
+        assert!((levels[0] - expected).abs() < 1e-3, "{}", levels[0]);
+    }
+
+    #[test]
+    fn floor_follows_a_louder_background() {
+        let settings = NoiseSettings::default();
+        let mut floor = NoiseFloor::new(1);
+        for _ in 0..200 {
+            floor.update(&mut [0.1], FRAME, &settings);
+        }
+        // Not within a fraction of the adaptation time
+        let mut levels = [0.];
+        for _ in 0..(settings.adaptation / 4. / FRAME) as usize {
+            levels[0] = 0.3;
+            floor.update(&mut levels, FRAME, &settings);
+        }
+        assert!(levels[0] > 0.2, "{}", levels[0]);
+        // But once it has lasted longer than the adaptation time
+        for _ in 0..(settings.adaptation * 1.25 / FRAME) as usize {
+            levels[0] = 0.3;
+            floor.update(&mut levels, FRAME, &settings);
+        }
+        assert_eq!(levels[0], 0.);
+    }
+}
@@ -0,0 +1,49 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::source::{ChannelLayout, Speaker};
+
+/// Where the user's speakers actually are, for rooms that don't follow the
+/// ITU-R BS.775 placement the radar assumes.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpeakerPlacement {
+    /// Azimuth in degrees clockwise from straight ahead, where it differs
+    /// from the speaker's nominal one.
+    pub azimuths: BTreeMap<Speaker, f32>,
+}
+
+impl SpeakerPlacement {
+    /// The azimuth `speaker` is drawn at, or `None` if it has no direction.
+    pub fn azimuth(&self, speaker: Speaker) -> Option<f32> {
+        let nominal = speaker.azimuth()?;
+        Some(self.azimuths.get(&speaker).copied().unwrap_or(nominal))
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, layout: &ChannelLayout) {
+        egui::Grid::new("placement").show(ui, |ui| {
+            for speaker in layout.speakers() {
+                let Some(mut azimuth) = self.azimuth(*speaker) else {
+                    continue;
+                };
+                ui.label(format!("{:?}", speaker));
+                let drag = egui::DragValue::new(&mut azimuth)
+                    .speed(1.)
+                    .clamp_range(-180.0..=180.0)
+                    .suffix("°");
+                if ui.add(drag).changed() {
+                    if Some(azimuth) == speaker.azimuth() {
+                        self.azimuths.remove(speaker);
+                    } else {
+                        self.azimuths.insert(*speaker, azimuth);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+
+        if ui.button("Reset").clicked() {
+            *self = Self::default();
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::{Duration, Instant};
+
+use crate::direction::Direction;
+
+/// A sound painted by the sweep, left to fade like phosphor.
+pub struct Echo {
+    /// The track the sound belongs to, if sounds are being tracked.
+    pub id: Option<u32>,
+    pub direction: Direction,
+    /// Estimated relative distance, from 0 near to 1 far.
+    pub distance: f32,
+    painted: Instant,
+}
+
+impl Echo {
+    /// How bright the echo still is at `now`, from 1 when painted down to 0
+    /// once `persistence` has passed.
+    pub fn brightness(&self, now: Instant, persistence: Duration) -> f32 {
+        1. - (now - self.painted).as_secs_f32() / persistence.as_secs_f32()
+    }
+}
+
+/// A rotating radar scan that only shows sounds when it passes over them.
+#[derive(Default)]
+pub struct Scope {
+    /// Where the sweep points, in degrees clockwise from straight ahead.
+    bearing: f32,
+    last: Option<Instant>,
+    echoes: Vec<Echo>,
+}
+
+impl Scope {
+    pub fn bearing(&self) -> f32 {
+        self.bearing
+    }
+
+    pub fn echoes(&self) -> &[Echo] {
+        &self.echoes
+    }
+
+    /// Turn the sweep up to `now` at `speed` degrees per second, painting an
+    /// echo of each of `sounds`, given with their distances, whose bearing it
+    /// passed and letting echoes older than `persistence` go.
+    pub fn sweep(
+        &mut self,
+        now: Instant,
+        speed: f32,
+        persistence: Duration,
+        sounds: &[(Option<u32>, Direction, f32)],
+    ) {
+        let elapsed = self.last.map_or(0., |last| (now - last).as_secs_f32());
+        self.last = Some(now);
+        let swept = elapsed * speed;
+
+        for (id, direction, distance) in sounds {
+            let offset = (direction.azimuth - self.bearing).rem_euclid(360.);
+            if offset < swept {
+                self.echoes.push(Echo {
+                    id: *id,
+                    direction: *direction,
+                    distance: *distance,
+                    painted: now,
+                });
+            }
+        }
+        self.bearing = (self.bearing + swept).rem_euclid(360.);
+        self.echoes
+            .retain(|echo| echo.brightness(now, persistence) > 0.);
+    }
+}
@@ -0,0 +1,576 @@
+use eframe::{egui, epaint::Color32};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::mapping::ChannelMap;
+use crate::placement::SpeakerPlacement;
+use crate::source::{ChannelLayout, Speaker};
+use crate::spectrum::SpectrumSettings;
+
+/// How levels map onto brightness and size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Curve {
+    /// Proportional to amplitude.
+    Linear,
+    /// Proportional to level in dB, between the floor and the ceiling.
+    Decibels,
+}
+
+/// The mapping from levels to what is drawn, shared by every view.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScaleSettings {
+    pub curve: Curve,
+    /// Levels at or below this draw as nothing.
+    pub floor_db: f32,
+    /// Levels at or above this draw at full strength.
+    pub ceiling_db: f32,
+    /// Exponent applied to the mapped level; above 1 favours loud sounds,
+    /// below 1 quiet ones.
+    pub gamma: f32,
+}
+
+impl Default for ScaleSettings {
+    fn default() -> Self {
+        Self {
+            curve: Curve::Decibels,
+            floor_db: -60.,
+            ceiling_db: 0.,
+            gamma: 1.,
+        }
+    }
+}
+
+impl ScaleSettings {
+    /// Map an amplitude onto 0..=1.
+    pub fn scale(&self, level: f32) -> f32 {
+        match self.curve {
+            Curve::Linear => {
+                let ceiling = 10f32.powf(self.ceiling_db / 20.);
+                self.shape(level / ceiling)
+            }
+            Curve::Decibels => self.scale_db(20. * level.max(1e-9).log10()),
+        }
+    }
+
+    /// Map a level in dB onto 0..=1.
+    pub fn scale_db(&self, level_db: f32) -> f32 {
+        match self.curve {
+            Curve::Linear => self.scale(10f32.powf(level_db / 20.)),
+            Curve::Decibels => {
+                let range = (self.ceiling_db - self.floor_db).max(1.);
+                self.shape((level_db - self.floor_db) / range)
+            }
+        }
+    }
+
+    fn shape(&self, level: f32) -> f32 {
+        level.clamp(0., 1.).powf(self.gamma)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.curve, Curve::Decibels, "dB");
+            ui.selectable_value(&mut self.curve, Curve::Linear, "Linear");
+        });
+        ui.add_enabled(
+            self.curve == Curve::Decibels,
+            egui::Slider::new(&mut self.floor_db, -120.0..=-6.0)
+                .text("Floor")
+                .suffix(" dBFS"),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.ceiling_db, -40.0..=0.0)
+                .text("Ceiling")
+                .suffix(" dBFS"),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.gamma, 0.2..=5.0)
+                .logarithmic(true)
+                .text("Curve"),
+        );
+    }
+}
+
+/// How the LFE channel is shown in the middle of the radar.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LfeSettings {
+    pub show: bool,
+    /// Applied on top of the channel trim; LFE content is usually mixed hot
+    /// and sustained, so it needs taming to leave headroom for transients.
+    pub gain_db: f32,
+}
+
+impl Default for LfeSettings {
+    fn default() -> Self {
+        Self {
+            show: true,
+            gain_db: -6.,
+        }
+    }
+}
+
+impl LfeSettings {
+    /// The fill of the inner disc for an LFE `level`.
+    pub fn color(&self, level: f32, scale: &ScaleSettings) -> Color32 {
+        let level = scale.scale(level * 10f32.powf(self.gain_db / 20.));
+        Color32::from_rgb((level * 160.) as u8, 0, (level * 255.) as u8)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.show, "Show LFE in the centre");
+        ui.add(
+            egui::Slider::new(&mut self.gain_db, -24.0..=12.0)
+                .text("Gain")
+                .suffix(" dB"),
+        );
+    }
+}
+
+/// The blip marking the estimated direction of the dominant sound.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlipSettings {
+    pub show: bool,
+    /// Separate and follow several sounds rather than showing the average
+    /// direction of everything.
+    pub track: bool,
+    /// Combined level below which no direction is shown.
+    pub threshold_db: f32,
+}
+
+impl Default for BlipSettings {
+    fn default() -> Self {
+        Self {
+            show: true,
+            track: true,
+            threshold_db: -40.,
+        }
+    }
+}
+
+impl BlipSettings {
+    pub fn threshold(&self) -> f32 {
+        10f32.powf(self.threshold_db / 20.)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.show, "Show the direction of sounds");
+        ui.checkbox(&mut self.track, "Track several sounds at once");
+        ui.add(
+            egui::Slider::new(&mut self.threshold_db, -80.0..=0.0)
+                .text("Threshold")
+                .suffix(" dB"),
+        );
+    }
+}
+
+/// Placing blips nearer the centre the closer their sound seems to be.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DistanceSettings {
+    pub show: bool,
+    /// Level of a sound right by the listener.
+    pub near_db: f32,
+    /// Level of a sound as far away as can be heard.
+    pub far_db: f32,
+}
+
+impl Default for DistanceSettings {
+    fn default() -> Self {
+        Self {
+            show: true,
+            near_db: -6.,
+            far_db: -48.,
+        }
+    }
+}
+
+impl DistanceSettings {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.show, "Place sounds by estimated distance");
+        ui.add_enabled(
+            self.show,
+            egui::Slider::new(&mut self.near_db, -40.0..=0.0)
+                .text("Near level")
+                .suffix(" dB"),
+        );
+        ui.add_enabled(
+            self.show,
+            egui::Slider::new(&mut self.far_db, -90.0..=-10.0)
+                .text("Far level")
+                .suffix(" dB"),
+        );
+        self.far_db = self.far_db.min(self.near_db - 1.);
+    }
+}
+
+/// The radar sweep, and whether sounds only show up as it passes them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SweepSettings {
+    /// Paint sounds as echoes when the sweep passes their bearing, rather
+    /// than showing them continuously.
+    pub scan: bool,
+    /// Degrees per second.
+    pub speed: f32,
+    /// Seconds an echo takes to fade away.
+    pub persistence: f32,
+}
+
+impl Default for SweepSettings {
+    fn default() -> Self {
+        Self {
+            scan: false,
+            speed: 100.,
+            persistence: 3.,
+        }
+    }
+}
+
+impl SweepSettings {
+    pub fn persistence(&self) -> Duration {
+        Duration::from_secs_f32(self.persistence)
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.scan, "Only show sounds as the sweep passes");
+        ui.add(
+            egui::Slider::new(&mut self.speed, 10.0..=720.0)
+                .text("Speed")
+                .suffix("°/s"),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.persistence, 0.2..=10.0)
+                .text("Persistence")
+                .suffix(" s"),
+        );
+    }
+}
+
+/// Bands of frequencies the radar can be limited to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandPreset {
+    FullRange,
+    /// Thuds and scuffs, below most gunfire and music.
+    Footsteps,
+    /// The crack of gunfire, above its boom and above explosions.
+    Gunshots,
+    /// The telephone band.
+    Voice,
+    Custom,
+}
+
+impl BandPreset {
+    const ALL: [BandPreset; 5] = [
+        BandPreset::FullRange,
+        BandPreset::Footsteps,
+        BandPreset::Gunshots,
+        BandPreset::Voice,
+        BandPreset::Custom,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            BandPreset::FullRange => "Full range",
+            BandPreset::Footsteps => "Footsteps",
+            BandPreset::Gunshots => "Gunshots",
+            BandPreset::Voice => "Voice",
+            BandPreset::Custom => "Custom",
+        }
+    }
+}
+
+/// Which frequencies the radar reacts to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilterSettings {
+    pub preset: BandPreset,
+    /// Corners of the custom band in Hz.
+    pub custom_low: f32,
+    pub custom_high: f32,
+}
+
+impl Default for FilterSettings {
+    fn default() -> Self {
+        Self {
+            preset: BandPreset::FullRange,
+            custom_low: 100.,
+            custom_high: 4000.,
+        }
+    }
+}
+
+impl FilterSettings {
+    /// The band to pass in Hz, or `None` for everything.
+    pub fn band(&self) -> Option<(f32, f32)> {
+        match self.preset {
+            BandPreset::FullRange => None,
+            BandPreset::Footsteps => Some((150., 1500.)),
+            BandPreset::Gunshots => Some((1500., 6000.)),
+            BandPreset::Voice => Some((300., 3400.)),
+            BandPreset::Custom => Some((self.custom_low, self.custom_high.max(self.custom_low))),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, has_audio: bool) {
+        if !has_audio {
+            ui.colored_label(
+                Color32::YELLOW,
+                "This source only reports levels; filtering needs one with audio",
+            );
+        }
+        egui::ComboBox::from_label("Band")
+            .selected_text(self.preset.name())
+            .show_ui(ui, |ui| {
+                for preset in BandPreset::ALL {
+                    ui.selectable_value(&mut self.preset, preset, preset.name());
+                }
+            });
+        if self.preset == BandPreset::Custom {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut self.custom_low)
+                        .speed(10.)
+                        .clamp_range(20.0..=20000.0)
+                        .suffix(" Hz"),
+                );
+                ui.label("to");
+                ui.add(
+                    egui::DragValue::new(&mut self.custom_high)
+                        .speed(10.)
+                        .clamp_range(20.0..=20000.0)
+                        .suffix(" Hz"),
+                );
+            });
+        }
+    }
+}
+
+/// Removal of the steady background, so that only sounds rising above it
+/// show up.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseSettings {
+    pub suppress: bool,
+    /// Seconds over which the background is tracked; sounds lasting longer
+    /// than this become part of it.
+    pub adaptation: f32,
+    /// How far above the background a sound must rise before it shows.
+    pub margin_db: f32,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            suppress: false,
+            adaptation: 5.,
+            margin_db: 3.,
+        }
+    }
+}
+
+impl NoiseSettings {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.suppress, "Suppress steady background sound");
+        ui.add_enabled(
+            self.suppress,
+            egui::Slider::new(&mut self.adaptation, 0.5..=30.0)
+                .logarithmic(true)
+                .text("Adaptation")
+                .suffix(" s"),
+        );
+        ui.add_enabled(
+            self.suppress,
+            egui::Slider::new(&mut self.margin_db, 0.0..=12.0)
+                .text("Margin")
+                .suffix(" dB"),
+        );
+    }
+}
+
+/// How the meters respond to changes in level.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BallisticsSettings {
+    /// Time to rise towards a louder level.
+    pub attack_ms: f32,
+    /// Time to fall towards a quieter level.
+    pub release_ms: f32,
+    /// Mark the recent peak of each sector.
+    pub peak_hold: bool,
+    /// How long a peak is held before it decays.
+    pub hold_ms: f32,
+    pub decay_db_per_s: f32,
+}
+
+impl Default for BallisticsSettings {
+    fn default() -> Self {
+        Self {
+            attack_ms: 5.,
+            release_ms: 300.,
+            peak_hold: true,
+            hold_ms: 1000.,
+            decay_db_per_s: 20.,
+        }
+    }
+}
+
+impl BallisticsSettings {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::Slider::new(&mut self.attack_ms, 0.0..=200.0)
+                .text("Attack")
+                .suffix(" ms"),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.release_ms, 0.0..=3000.0)
+                .text("Release")
+                .suffix(" ms"),
+        );
+        ui.checkbox(&mut self.peak_hold, "Peak hold");
+        ui.add_enabled(
+            self.peak_hold,
+            egui::Slider::new(&mut self.hold_ms, 0.0..=5000.0)
+                .text("Hold")
+                .suffix(" ms"),
+        );
+        ui.add_enabled(
+            self.peak_hold,
+            egui::Slider::new(&mut self.decay_db_per_s, 1.0..=120.0)
+                .text("Decay")
+                .suffix(" dB/s"),
+        );
+    }
+}
+
+/// Detection of discrete sounds from their onsets.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventSettings {
+    pub detect: bool,
+    /// How far a channel must jump above its recent level to start an event.
+    pub sensitivity_db: f32,
+    /// Quietest level that can start an event.
+    pub min_level_db: f32,
+    /// How closely an event must match a template to be recognised as it,
+    /// as a correlation from 0 to 1.
+    pub match_threshold: f32,
+    /// Lowest score of the sound model's best class for an event to be
+    /// recognised as it; below this the heuristics decide.
+    pub min_model_score: f32,
+    /// Show the list of recent events.
+    pub show_log: bool,
+}
+
+impl Default for EventSettings {
+    fn default() -> Self {
+        Self {
+            detect: true,
+            sensitivity_db: 9.,
+            min_level_db: -50.,
+            match_threshold: 0.8,
+            min_model_score: 0.5,
+            show_log: false,
+        }
+    }
+}
+
+impl EventSettings {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.detect, "Detect sound events");
+        ui.add_enabled(
+            self.detect,
+            egui::Slider::new(&mut self.sensitivity_db, 3.0..=24.0)
+                .text("Onset jump")
+                .suffix(" dB"),
+        );
+        ui.add_enabled(
+            self.detect,
+            egui::Slider::new(&mut self.min_level_db, -90.0..=0.0)
+                .text("Minimum level")
+                .suffix(" dBFS"),
+        );
+        ui.add_enabled(
+            self.detect,
+            egui::Slider::new(&mut self.match_threshold, 0.5..=1.0).text("Template match"),
+        );
+        ui.add_enabled(
+            self.detect,
+            egui::Slider::new(&mut self.min_model_score, 0.0..=1.0).text("Model confidence"),
+        );
+    }
+}
+
+/// Everything the user can configure, persisted between runs by eframe.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub channel_map: ChannelMap,
+    pub scale: ScaleSettings,
+    pub filter: FilterSettings,
+    pub noise: NoiseSettings,
+    pub ballistics: BallisticsSettings,
+    pub events: EventSettings,
+    pub placement: SpeakerPlacement,
+    pub lfe: LfeSettings,
+    pub blip: BlipSettings,
+    pub distance: DistanceSettings,
+    pub sweep: SweepSettings,
+    pub spectrum: SpectrumSettings,
+}
+
+impl Settings {
+    pub fn load(storage: Option<&dyn eframe::Storage>) -> Self {
+        storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
+
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        layout: &ChannelLayout,
+        speakers: &[Speaker],
+        has_audio: bool,
+    ) {
+        egui::CollapsingHeader::new("Channel mapping").show(ui, |ui| {
+            self.channel_map.ui(ui, layout, speakers);
+        });
+        egui::CollapsingHeader::new("Scale").show(ui, |ui| {
+            self.scale.ui(ui);
+        });
+        egui::CollapsingHeader::new("Frequency filter").show(ui, |ui| {
+            self.filter.ui(ui, has_audio);
+        });
+        egui::CollapsingHeader::new("Background").show(ui, |ui| {
+            self.noise.ui(ui);
+        });
+        egui::CollapsingHeader::new("Ballistics").show(ui, |ui| {
+            self.ballistics.ui(ui);
+        });
+        egui::CollapsingHeader::new("Events").show(ui, |ui| {
+            self.events.ui(ui);
+        });
+        egui::CollapsingHeader::new("Speaker placement").show(ui, |ui| {
+            self.placement.ui(ui, layout);
+        });
+        egui::CollapsingHeader::new("LFE").show(ui, |ui| {
+            self.lfe.ui(ui);
+        });
+        egui::CollapsingHeader::new("Direction").show(ui, |ui| {
+            self.blip.ui(ui);
+        });
+        egui::CollapsingHeader::new("Distance").show(ui, |ui| {
+            self.distance.ui(ui);
+        });
+        egui::CollapsingHeader::new("Sweep").show(ui, |ui| {
+            self.sweep.ui(ui);
+        });
+    }
+}
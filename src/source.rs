@@ -0,0 +1,414 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[cfg(feature = "alsa")]
+pub mod alsa;
+pub mod capture;
+pub mod file;
+#[cfg(feature = "pulse")]
+pub mod pulse;
+pub mod stdin;
+pub mod synth;
+#[cfg(windows)]
+pub mod wasapi;
+
+/// A physical speaker position that a channel can feed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Speaker {
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    LowFrequency,
+    BackLeft,
+    BackRight,
+    BackCenter,
+    SideLeft,
+    SideRight,
+    TopFrontLeft,
+    TopFrontRight,
+    TopBackLeft,
+    TopBackRight,
+}
+
+/// WAVE_FORMAT_EXTENSIBLE channel mask bits (`SPEAKER_*`) in channel order.
+static MASK_BITS: [(u32, Speaker); 13] = [
+    (0x1, Speaker::FrontLeft),
+    (0x2, Speaker::FrontRight),
+    (0x4, Speaker::FrontCenter),
+    (0x8, Speaker::LowFrequency),
+    (0x10, Speaker::BackLeft),
+    (0x20, Speaker::BackRight),
+    (0x100, Speaker::BackCenter),
+    (0x200, Speaker::SideLeft),
+    (0x400, Speaker::SideRight),
+    (0x1000, Speaker::TopFrontLeft),
+    (0x4000, Speaker::TopFrontRight),
+    (0x8000, Speaker::TopBackLeft),
+    (0x20000, Speaker::TopBackRight),
+];
+
+impl Speaker {
+    /// Nominal direction in degrees clockwise from straight ahead, following
+    /// ITU-R BS.775 for 7.1 and Dolby's placement for the height speakers.
+    /// The LFE channel has no direction.
+    pub fn azimuth(self) -> Option<f32> {
+        match self {
+            Speaker::FrontCenter => Some(0.),
+            Speaker::FrontRight => Some(30.),
+            Speaker::SideRight => Some(90.),
+            Speaker::BackRight => Some(150.),
+            Speaker::BackCenter => Some(180.),
+            Speaker::BackLeft => Some(-150.),
+            Speaker::SideLeft => Some(-90.),
+            Speaker::FrontLeft => Some(-30.),
+            Speaker::TopFrontRight => Some(45.),
+            Speaker::TopBackRight => Some(135.),
+            Speaker::TopBackLeft => Some(-135.),
+            Speaker::TopFrontLeft => Some(-45.),
+            Speaker::LowFrequency => None,
+        }
+    }
+
+    /// Whether the speaker sits above the listener rather than at ear level.
+    pub fn is_overhead(self) -> bool {
+        matches!(
+            self,
+            Speaker::TopFrontLeft
+                | Speaker::TopFrontRight
+                | Speaker::TopBackLeft
+                | Speaker::TopBackRight
+        )
+    }
+}
+
+/// Speaker layouts that can be named on the command line, for sources whose
+/// channels carry no layout of their own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NamedLayout {
+    Mono,
+    Stereo,
+    Quad,
+    #[value(name = "5.1")]
+    Surround51,
+    /// 5.1 with side rather than back surrounds
+    #[value(name = "5.1-side")]
+    Surround51Side,
+    #[value(name = "7.1")]
+    Surround71,
+    #[value(name = "5.1.2")]
+    Surround512,
+    #[value(name = "5.1.4")]
+    Surround514,
+    #[value(name = "7.1.2")]
+    Surround712,
+    #[value(name = "7.1.4")]
+    Surround714,
+}
+
+impl NamedLayout {
+    /// The WAVE_FORMAT_EXTENSIBLE channel mask of the layout.
+    fn mask(self) -> u32 {
+        match self {
+            NamedLayout::Mono => 0x4,
+            NamedLayout::Stereo => 0x3,
+            NamedLayout::Quad => 0x33,
+            NamedLayout::Surround51 => 0x3f,
+            NamedLayout::Surround51Side => 0x60f,
+            NamedLayout::Surround71 => 0x63f,
+            NamedLayout::Surround512 => 0x503f,
+            NamedLayout::Surround514 => 0x2d03f,
+            NamedLayout::Surround712 => 0x563f,
+            NamedLayout::Surround714 => 0x2d63f,
+        }
+    }
+
+    /// The layout, with channels in channel mask order.
+    pub fn layout(self) -> ChannelLayout {
+        // Every mask above is made of known bits
+        ChannelLayout::from_mask(self.mask()).unwrap()
+    }
+}
+
+/// The speaker fed by each channel index of a level source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelLayout {
+    speakers: Vec<Speaker>,
+}
+
+impl ChannelLayout {
+    pub fn new(speakers: Vec<Speaker>) -> Self {
+        Self { speakers }
+    }
+
+    /// The WAVE_FORMAT_EXTENSIBLE ordering of 7.1 audio.
+    pub fn surround_7_1() -> Self {
+        Self::new(vec![
+            Speaker::FrontLeft,
+            Speaker::FrontRight,
+            Speaker::FrontCenter,
+            Speaker::LowFrequency,
+            Speaker::BackLeft,
+            Speaker::BackRight,
+            Speaker::SideLeft,
+            Speaker::SideRight,
+        ])
+    }
+
+    /// The layout described by a WAVE_FORMAT_EXTENSIBLE channel mask, where
+    /// channels appear in the order of their mask bits. `None` if the mask
+    /// includes a speaker we have no place for on the radar.
+    pub fn from_mask(mask: u32) -> Option<Self> {
+        let known = MASK_BITS.iter().fold(0, |known, (bit, _)| known | bit);
+        if mask == 0 || mask & !known != 0 {
+            return None;
+        }
+        Some(Self::new(
+            MASK_BITS
+                .iter()
+                .filter(|(bit, _)| mask & bit != 0)
+                .map(|(_, speaker)| *speaker)
+                .collect(),
+        ))
+    }
+
+    /// The layout of `channels` channels described by channel mask `mask`,
+    /// but only if it accounts for exactly those channels, or else the
+    /// default layout for the channel count. Used by the WASAPI backends.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn for_channels(channels: usize, mask: Option<u32>) -> Option<Self> {
+        mask.and_then(Self::from_mask)
+            .filter(|layout| layout.len() == channels)
+            .or_else(|| {
+                warn!(
+                    "Falling back to the default layout for {} channels",
+                    channels
+                );
+                Self::default_for(channels)
+            })
+    }
+
+    /// The WAVE_FORMAT_EXTENSIBLE default layout for a channel count, 3.0 for
+    /// 3 channels, or the usual height layout for 10 and 12 channels, as used
+    /// for streams that carry no channel mask.
+    pub fn default_for(channels: usize) -> Option<Self> {
+        use Speaker::*;
+        let speakers = match channels {
+            1 => vec![FrontCenter],
+            2 => vec![FrontLeft, FrontRight],
+            3 => vec![FrontLeft, FrontRight, FrontCenter],
+            4 => vec![FrontLeft, FrontRight, BackLeft, BackRight],
+            6 => vec![
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+            ],
+            7 => vec![
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackCenter,
+                SideLeft,
+                SideRight,
+            ],
+            8 => return Some(Self::surround_7_1()),
+            // 5.1.4 and 7.1.4; 5.1.2 and 7.1.2 need a channel mask or `NamedLayout`
+            10 => vec![
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+                TopFrontLeft,
+                TopFrontRight,
+                TopBackLeft,
+                TopBackRight,
+            ],
+            12 => vec![
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                BackLeft,
+                BackRight,
+                SideLeft,
+                SideRight,
+                TopFrontLeft,
+                TopFrontRight,
+                TopBackLeft,
+                TopBackRight,
+            ],
+            _ => return None,
+        };
+        Some(Self::new(speakers))
+    }
+
+    pub fn len(&self) -> usize {
+        self.speakers.len()
+    }
+
+    pub fn speakers(&self) -> &[Speaker] {
+        &self.speakers
+    }
+
+    /// The channel index feeding `speaker`, if the layout has one.
+    pub fn position(&self, speaker: Speaker) -> Option<usize> {
+        self.speakers.iter().position(|s| *s == speaker)
+    }
+}
+
+/// Anything that can report a peak level per channel, e.g. an endpoint meter.
+pub trait LevelSource {
+    fn layout(&self) -> &ChannelLayout;
+
+    fn channel_count(&self) -> usize {
+        self.layout().len()
+    }
+
+    /// Fill `levels` with the current peak of each channel in the range 0..=1.
+    /// `levels` holds `channel_count()` entries.
+    fn read_levels(&mut self, levels: &mut [f32]) -> Result<()>;
+
+    /// The sample rate of the audio behind the levels, for sources that see
+    /// the audio itself rather than a meter.
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    /// Move the interleaved samples captured since the last call onto the end
+    /// of `samples`. Sources without audio leave it untouched.
+    fn read_samples(&mut self, _samples: &mut Vec<f32>) {}
+
+    /// Playback controls, for sources that read from a recording.
+    fn transport(&mut self) -> Option<&mut dyn Transport> {
+        None
+    }
+}
+
+/// Pause and seek controls of a recording being played through the radar.
+pub trait Transport {
+    fn position(&self) -> Duration;
+
+    /// The length of the recording, if known.
+    fn duration(&self) -> Option<Duration>;
+
+    fn is_paused(&self) -> bool;
+
+    fn set_paused(&mut self, paused: bool);
+
+    fn seek(&mut self, position: Duration);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Speaker::*;
+
+    /// KSAUDIO_SPEAKER_7POINT1_SURROUND: sides at 0x600, backs at 0x30.
+    static MASK_7_1: u32 = 0x63f;
+
+    #[test]
+    fn seven_one_mask_orders_back_before_side() {
+        let layout = ChannelLayout::from_mask(MASK_7_1).unwrap();
+        assert_eq!(layout, ChannelLayout::surround_7_1());
+        assert_eq!(layout.position(BackLeft), Some(4));
+        assert_eq!(layout.position(SideLeft), Some(6));
+    }
+
+    #[test]
+    fn five_one_side_mask_has_no_back_speakers() {
+        // KSAUDIO_SPEAKER_5POINT1_SURROUND
+        let layout = ChannelLayout::from_mask(0x60f).unwrap();
+        assert_eq!(
+            layout.speakers(),
+            [
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                LowFrequency,
+                SideLeft,
+                SideRight
+            ]
+        );
+        assert_eq!(layout.position(BackLeft), None);
+    }
+
+    #[test]
+    fn unknown_mask_bit_has_no_layout() {
+        // SPEAKER_FRONT_LEFT_OF_CENTER
+        assert_eq!(ChannelLayout::from_mask(0x3 | 0x40), None);
+        assert_eq!(ChannelLayout::from_mask(0), None);
+    }
+
+    #[test]
+    fn default_layouts_match_channel_counts() {
+        for channels in [1, 2, 3, 4, 6, 7, 8, 10, 12] {
+            assert_eq!(
+                ChannelLayout::default_for(channels).unwrap().len(),
+                channels
+            );
+        }
+        assert_eq!(ChannelLayout::default_for(5), None);
+        assert_eq!(
+            ChannelLayout::default_for(8),
+            Some(ChannelLayout::surround_7_1())
+        );
+    }
+
+    #[test]
+    fn named_layouts_match_the_defaults_and_have_heights() {
+        assert_eq!(
+            NamedLayout::Surround71.layout(),
+            ChannelLayout::surround_7_1()
+        );
+        assert_eq!(
+            Some(NamedLayout::Surround514.layout()),
+            ChannelLayout::default_for(10)
+        );
+        assert_eq!(
+            Some(NamedLayout::Surround714.layout()),
+            ChannelLayout::default_for(12)
+        );
+        for (name, channels, overhead) in [
+            (NamedLayout::Surround512, 8, 2),
+            (NamedLayout::Surround712, 10, 2),
+            (NamedLayout::Surround51Side, 6, 0),
+        ] {
+            let layout = name.layout();
+            assert_eq!(layout.len(), channels, "{:?}", name);
+            let heights = layout.speakers().iter().filter(|s| s.is_overhead());
+            assert_eq!(heights.count(), overhead, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn mask_is_used_when_it_covers_the_channels() {
+        // 5.1 with side speakers, which the six channel default lacks
+        let layout = ChannelLayout::for_channels(6, Some(0x60f)).unwrap();
+        assert_eq!(layout.position(SideLeft), Some(4));
+    }
+
+    #[test]
+    fn mask_of_the_wrong_length_falls_back_to_the_default() {
+        assert_eq!(
+            ChannelLayout::for_channels(8, Some(0x60f)),
+            ChannelLayout::default_for(8)
+        );
+        assert_eq!(
+            ChannelLayout::for_channels(2, Some(0x3 | 0x40)),
+            ChannelLayout::default_for(2)
+        );
+        assert_eq!(
+            ChannelLayout::for_channels(2, None),
+            ChannelLayout::default_for(2)
+        );
+        assert_eq!(ChannelLayout::for_channels(5, Some(0x1f | 0x40)), None);
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, bail, Result};
+use log::*;
+
+use alsa::{
+    pcm::{Access, ChmapPosition, Format, HwParams, PCM},
+    Direction, ValueOr,
+};
+
+use super::capture::{CaptureSource, PcmReader, BLOCK_FRAMES};
+use super::{ChannelLayout, Speaker};
+
+static SAMPLE_RATE: u32 = 48000;
+
+/// ALSA's own channel order, used when a device of 4 to 8 channels has no
+/// channel map.
+static DEFAULT_ORDER: [Speaker; 8] = [
+    Speaker::FrontLeft,
+    Speaker::FrontRight,
+    Speaker::BackLeft,
+    Speaker::BackRight,
+    Speaker::FrontCenter,
+    Speaker::LowFrequency,
+    Speaker::SideLeft,
+    Speaker::SideRight,
+];
+
+fn speaker(position: ChmapPosition) -> Option<Speaker> {
+    match position {
+        ChmapPosition::FL => Some(Speaker::FrontLeft),
+        ChmapPosition::FR => Some(Speaker::FrontRight),
+        ChmapPosition::FC => Some(Speaker::FrontCenter),
+        ChmapPosition::LFE => Some(Speaker::LowFrequency),
+        ChmapPosition::RL => Some(Speaker::BackLeft),
+        ChmapPosition::RR => Some(Speaker::BackRight),
+        ChmapPosition::RC => Some(Speaker::BackCenter),
+        ChmapPosition::SL => Some(Speaker::SideLeft),
+        ChmapPosition::SR => Some(Speaker::SideRight),
+        // HDMI sinks report front height speakers rather than top front ones
+        ChmapPosition::TFL | ChmapPosition::FLH => Some(Speaker::TopFrontLeft),
+        ChmapPosition::TFR | ChmapPosition::FRH => Some(Speaker::TopFrontRight),
+        ChmapPosition::TRL => Some(Speaker::TopBackLeft),
+        ChmapPosition::TRR => Some(Speaker::TopBackRight),
+        _ => None,
+    }
+}
+
+/// The layout the device reports, falling back to ALSA's default order for
+/// 4 to 8 channels. ALSA's order puts the rear pair third and fourth, which
+/// suits fewer channels poorly, so those get the usual defaults instead.
+fn layout(pcm: &PCM, channels: usize) -> Option<ChannelLayout> {
+    let mapped = pcm.get_chmap().ok().and_then(|chmap| {
+        let positions: Vec<ChmapPosition> = (&chmap).into();
+        positions
+            .into_iter()
+            .map(speaker)
+            .collect::<Option<Vec<_>>>()
+    });
+    match mapped {
+        Some(speakers) if speakers.len() == channels => Some(ChannelLayout::new(speakers)),
+        _ if (4..=DEFAULT_ORDER.len()).contains(&channels) => {
+            Some(ChannelLayout::new(DEFAULT_ORDER[..channels].to_vec()))
+        }
+        // ALSA has no default order for height layouts
+        _ => ChannelLayout::default_for(channels),
+    }
+}
+
+struct AlsaReader {
+    pcm: PCM,
+    channels: usize,
+    samples: Vec<i16>,
+}
+
+impl PcmReader for AlsaReader {
+    fn read(&mut self, samples: &mut [f32]) -> Result<usize> {
+        let raw = &mut self.samples[..samples.len()];
+        let io = self.pcm.io_i16()?;
+        let frames = loop {
+            match io.readi(raw) {
+                Ok(frames) => break frames,
+                Err(e) => {
+                    // Overruns are routine when the UI stalls; restart the stream.
+                    debug!("Recovering ALSA capture: {}", e);
+                    self.pcm.try_recover(e, true)?;
+                }
+            }
+        };
+        let read = frames * self.channels;
+        for (sample, raw) in samples.iter_mut().zip(&raw[..read]) {
+            *sample = *raw as f32 / 32768.;
+        }
+        Ok(read)
+    }
+}
+
+fn open(device: &str, channels: usize) -> Result<(PCM, ChannelLayout, u32)> {
+    info!("Opening ALSA device {} for {} channels", device, channels);
+    let pcm = PCM::new(device, Direction::Capture, false)?;
+    let sample_rate = {
+        let hwp = HwParams::any(&pcm)?;
+        hwp.set_channels(channels as u32)?;
+        let sample_rate = hwp.set_rate_near(SAMPLE_RATE, ValueOr::Nearest)?;
+        hwp.set_format(Format::s16())?;
+        hwp.set_access(Access::RWInterleaved)?;
+        hwp.set_period_size_near(BLOCK_FRAMES as i64, ValueOr::Nearest)?;
+        pcm.hw_params(&hwp)?;
+        sample_rate
+    };
+    pcm.start()?;
+    let layout = layout(&pcm, channels)
+        .ok_or_else(|| anyhow!("No speaker layout for {} channels", channels))?;
+    Ok((pcm, layout, sample_rate))
+}
+
+/// Capture `channels` channels from the ALSA PCM `device`, e.g. `hw:Loopback,1`,
+/// as `layout` if given rather than as the device says.
+pub fn capture(
+    device: &str,
+    channels: usize,
+    layout: Option<ChannelLayout>,
+) -> Result<CaptureSource> {
+    if channels == 0
+        || (channels > DEFAULT_ORDER.len() && ChannelLayout::default_for(channels).is_none())
+    {
+        bail!(
+            "ALSA capture supports 1 to {}, 10 or 12 channels, not {}",
+            DEFAULT_ORDER.len(),
+            channels
+        );
+    }
+
+    // The layout is only known once the device is open, so open it here and
+    // hand it to the capture thread; `PCM` is `Send`.
+    let (pcm, reported, sample_rate) = open(device, channels)
+        .map_err(|e| anyhow!("Failed to open ALSA device {}: {}", device, e))?;
+    let layout = layout.unwrap_or(reported);
+    info!("ALSA channel layout {:?} at {}Hz", layout, sample_rate);
+
+    CaptureSource::spawn("alsa", layout, sample_rate, move || {
+        Ok(AlsaReader {
+            pcm,
+            channels,
+            samples: vec![0; BLOCK_FRAMES * channels],
+        })
+    })
+}
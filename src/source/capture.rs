@@ -0,0 +1,166 @@
+use anyhow::{anyhow, Result};
+use log::*;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{ChannelLayout, LevelSource};
+
+/// Frames read from a PCM stream per block; roughly 10ms at 48kHz.
+pub const BLOCK_FRAMES: usize = 480;
+
+/// A blocking stream of interleaved f32 PCM.
+pub trait PcmReader {
+    /// Fill `samples` with interleaved samples, returning how many were written.
+    /// Zero means the stream has ended.
+    fn read(&mut self, samples: &mut [f32]) -> Result<usize>;
+}
+
+/// Holds a reader back to real time, for streams that can be read faster.
+pub struct Pacer {
+    sample_rate: u32,
+    started: Instant,
+    frames: u64,
+}
+
+impl Pacer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            started: Instant::now(),
+            frames: 0,
+        }
+    }
+
+    /// Start counting from now, e.g. after a pause or seek.
+    pub fn restart(&mut self) {
+        self.started = Instant::now();
+        self.frames = 0;
+    }
+
+    /// Audio time handed over since the last restart.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / self.sample_rate as f64)
+    }
+
+    /// Account for `frames` more frames, sleeping until they are due.
+    pub fn wait(&mut self, frames: usize) {
+        self.frames += frames as u64;
+        if let Some(wait) = (self.started + self.elapsed()).checked_duration_since(Instant::now()) {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Most PCM held for `read_samples`, so that nothing piles up when no one reads it.
+static MAX_PENDING: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct Shared {
+    /// Per channel peak since the last `read_levels`.
+    peaks: Vec<f32>,
+    /// Interleaved samples since the last `read_samples`.
+    samples: Vec<f32>,
+    error: Option<String>,
+}
+
+/// A level source fed by a capture thread that meters raw PCM.
+pub struct CaptureSource {
+    layout: ChannelLayout,
+    sample_rate: u32,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl CaptureSource {
+    /// Start a thread that opens a reader with `open` and meters everything it reads.
+    /// The reader is created on the capture thread, so it need not be `Send`.
+    pub fn spawn<R, F>(name: &str, layout: ChannelLayout, sample_rate: u32, open: F) -> Result<Self>
+    where
+        R: PcmReader,
+        F: FnOnce() -> Result<R> + Send + 'static,
+    {
+        let channels = layout.len();
+        let max_pending = (MAX_PENDING.as_secs_f64() * sample_rate as f64) as usize * channels;
+        let shared = Arc::new(Mutex::new(Shared {
+            peaks: vec![0.; channels],
+            samples: Vec::new(),
+            error: None,
+        }));
+        let (opened_tx, opened_rx) = mpsc::sync_channel(1);
+
+        let thread_shared = shared.clone();
+        std::thread::Builder::new()
+            .name(format!("{} capture", name))
+            .spawn(move || {
+                let mut reader = match open() {
+                    Ok(reader) => {
+                        let _ = opened_tx.send(Ok(()));
+                        reader
+                    }
+                    Err(e) => {
+                        let _ = opened_tx.send(Err(e));
+                        return;
+                    }
+                };
+
+                let mut block = vec![0.; BLOCK_FRAMES * channels];
+                loop {
+                    let error = match reader.read(&mut block) {
+                        Ok(0) => "Stream ended".to_string(),
+                        Ok(read) => {
+                            let mut shared = thread_shared.lock().unwrap();
+                            for frame in block[..read].chunks(channels) {
+                                for (peak, sample) in shared.peaks.iter_mut().zip(frame) {
+                                    *peak = peak.max(sample.abs());
+                                }
+                            }
+                            shared.samples.extend_from_slice(&block[..read]);
+                            let excess = shared.samples.len().saturating_sub(max_pending);
+                            // Drop whole frames, so channels stay aligned
+                            shared.samples.drain(..excess - excess % channels);
+                            continue;
+                        }
+                        Err(e) => format!("{:#}", e),
+                    };
+                    warn!("Capture stopped: {}", error);
+                    thread_shared.lock().unwrap().error = Some(error);
+                    return;
+                }
+            })?;
+
+        opened_rx
+            .recv()
+            .map_err(|_| anyhow!("Capture thread exited during startup"))??;
+
+        Ok(Self {
+            layout,
+            sample_rate,
+            shared,
+        })
+    }
+}
+
+impl LevelSource for CaptureSource {
+    fn layout(&self) -> &ChannelLayout {
+        &self.layout
+    }
+
+    fn read_levels(&mut self, levels: &mut [f32]) -> Result<()> {
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(error) = &shared.error {
+            return Err(anyhow!("{}", error));
+        }
+        for (level, peak) in levels.iter_mut().zip(shared.peaks.iter_mut()) {
+            *level = peak.min(1.);
+            *peak = 0.;
+        }
+        Ok(())
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(self.sample_rate)
+    }
+
+    fn read_samples(&mut self, samples: &mut Vec<f32>) {
+        samples.append(&mut self.shared.lock().unwrap().samples);
+    }
+}
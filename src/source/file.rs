@@ -0,0 +1,318 @@
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use symphonia::core::{
+    audio::{Channels, SampleBuffer},
+    codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as DecodeError,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+    units::Time,
+};
+
+use super::capture::{CaptureSource, Pacer, PcmReader};
+use super::{ChannelLayout, LevelSource, Transport};
+
+/// Playback state shared between the UI and the decoding thread.
+#[derive(Default)]
+struct Control {
+    paused: bool,
+    /// Set once the end of the file is reached; playing again restarts it.
+    ended: bool,
+    seek: Option<Duration>,
+    position: Duration,
+}
+
+/// The layout for a channel mask, in the order symphonia decodes channels.
+fn layout(channels: Channels) -> Result<ChannelLayout> {
+    // Files without a channel mask, such as plain PCM WAV, are given the
+    // lowest N mask bits, which from eight channels up misreports the side
+    // pair as a front centre pair and has no height speakers.
+    let count = channels.count();
+    if count >= 8 && channels.bits() == (1 << count) - 1 {
+        if let Some(layout) = ChannelLayout::default_for(count) {
+            return Ok(layout);
+        }
+    }
+
+    // Symphonia's channel bits are the WAVE_FORMAT_EXTENSIBLE speaker bits.
+    ChannelLayout::from_mask(channels.bits())
+        .ok_or_else(|| anyhow!("Unsupported speaker positions {:?}", channels))
+}
+
+/// The audio track of a file, decoding a packet at a time.
+struct AudioTrack {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    channels: Channels,
+    /// Length of the track, if the file says.
+    frames: Option<u64>,
+    decoded: Option<SampleBuffer<f32>>,
+}
+
+impl AudioTrack {
+    fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+        let stream = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
+        }
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| anyhow!("{} has no audio track", path.display()))?;
+        let params = &track.codec_params;
+        let track_id = track.id;
+        let Some(sample_rate) = params.sample_rate else {
+            bail!("{} has no sample rate", path.display());
+        };
+        let Some(channels) = params.channels else {
+            bail!("{} has no channel layout", path.display());
+        };
+        let frames = params.n_frames;
+        let decoder = symphonia::default::get_codecs().make(params, &DecoderOptions::default())?;
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            sample_rate,
+            channels,
+            frames,
+            decoded: None,
+        })
+    }
+
+    /// Decode the next packet onto `samples`, returning false at the end of the file.
+    fn decode_packet(&mut self, samples: &mut Vec<f32>) -> Result<bool> {
+        let packet = match self.format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Ok(false)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != self.track_id {
+            return Ok(true);
+        }
+
+        match self.decoder.decode(&packet) {
+            Ok(audio) => {
+                let needed = audio.capacity() * self.channels.count();
+                if self.decoded.as_ref().is_none_or(|b| b.capacity() < needed) {
+                    self.decoded = Some(SampleBuffer::new(audio.capacity() as u64, *audio.spec()));
+                }
+                let decoded = self.decoded.as_mut().unwrap();
+                decoded.copy_interleaved_ref(audio);
+                samples.extend_from_slice(decoded.samples());
+            }
+            Err(DecodeError::DecodeError(e)) => warn!("Skipping corrupt packet: {}", e),
+            Err(e) => return Err(e.into()),
+        }
+        Ok(true)
+    }
+}
+
+/// Decode the whole audio file at `path` to interleaved samples, with its
+/// sample rate and channel count.
+pub fn decode(path: &Path) -> Result<(Vec<f32>, u32, usize)> {
+    let mut track = AudioTrack::open(path)?;
+    let mut samples = Vec::new();
+    while track.decode_packet(&mut samples)? {}
+    Ok((samples, track.sample_rate, track.channels.count()))
+}
+
+struct FileReader {
+    track: AudioTrack,
+    control: Arc<Mutex<Control>>,
+    /// Decoded samples not yet handed to the capture thread.
+    pending: Vec<f32>,
+    /// File position at which playback was last (re)started.
+    started_at: Duration,
+    pacer: Pacer,
+}
+
+impl FileReader {
+    fn seek(&mut self, position: Duration) -> Result<()> {
+        let track = &mut self.track;
+        let seeked = track.format.seek(
+            SeekMode::Coarse,
+            SeekTo::Time {
+                time: Time::from(position.as_secs_f64()),
+                track_id: Some(track.track_id),
+            },
+        )?;
+        track.decoder.reset();
+        let position = Duration::from_secs_f64(seeked.actual_ts as f64 / track.sample_rate as f64);
+        self.pending.clear();
+        self.restart_clock(position);
+        Ok(())
+    }
+
+    fn restart_clock(&mut self, position: Duration) {
+        self.started_at = position;
+        self.pacer.restart();
+    }
+
+    fn position(&self) -> Duration {
+        self.started_at + self.pacer.elapsed()
+    }
+}
+
+impl PcmReader for FileReader {
+    fn read(&mut self, samples: &mut [f32]) -> Result<usize> {
+        let channels = self.track.channels.count();
+        let block = Duration::from_secs_f64(
+            (samples.len() / channels) as f64 / self.track.sample_rate as f64,
+        );
+
+        loop {
+            let (paused, seek) = {
+                let mut control = self.control.lock().unwrap();
+                (control.paused, control.seek.take())
+            };
+            if let Some(position) = seek {
+                self.seek(position)?;
+                self.control.lock().unwrap().position = self.position();
+            }
+            if !paused {
+                break;
+            }
+            // Hand nothing over while paused; the radar holds its picture
+            std::thread::sleep(block);
+            self.restart_clock(self.position());
+        }
+
+        while self.pending.len() < samples.len() {
+            if !self.track.decode_packet(&mut self.pending)? {
+                info!("Reached the end of the file");
+                let mut control = self.control.lock().unwrap();
+                control.paused = true;
+                control.ended = true;
+                break;
+            }
+        }
+
+        let read = samples.len().min(self.pending.len());
+        samples[..read].copy_from_slice(&self.pending[..read]);
+        samples[read..].fill(0.);
+        self.pending.drain(..read);
+
+        // Hand blocks over in real time rather than as fast as they decode.
+        self.pacer.wait(read / channels);
+        self.control.lock().unwrap().position = self.position();
+
+        Ok(samples.len())
+    }
+}
+
+/// Plays a multichannel audio file through the radar in real time.
+pub struct FileSource {
+    capture: CaptureSource,
+    control: Arc<Mutex<Control>>,
+    duration: Option<Duration>,
+}
+
+impl FileSource {
+    pub fn open(path: &Path) -> Result<Self> {
+        let track = AudioTrack::open(path)?;
+        let sample_rate = track.sample_rate;
+        let layout = layout(track.channels)?;
+        let duration = track
+            .frames
+            .map(|frames| Duration::from_secs_f64(frames as f64 / sample_rate as f64));
+        info!(
+            "Playing {} at {}Hz with layout {:?}",
+            path.display(),
+            sample_rate,
+            layout
+        );
+
+        let control = Arc::new(Mutex::new(Control::default()));
+        let reader_control = control.clone();
+        let capture = CaptureSource::spawn("file", layout, sample_rate, move || {
+            Ok(FileReader {
+                track,
+                control: reader_control,
+                pending: Vec::new(),
+                started_at: Duration::ZERO,
+                pacer: Pacer::new(sample_rate),
+            })
+        })?;
+
+        Ok(Self {
+            capture,
+            control,
+            duration,
+        })
+    }
+}
+
+impl LevelSource for FileSource {
+    fn layout(&self) -> &ChannelLayout {
+        self.capture.layout()
+    }
+
+    fn read_levels(&mut self, levels: &mut [f32]) -> Result<()> {
+        self.capture.read_levels(levels)
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        self.capture.sample_rate()
+    }
+
+    fn read_samples(&mut self, samples: &mut Vec<f32>) {
+        self.capture.read_samples(samples)
+    }
+
+    fn transport(&mut self) -> Option<&mut dyn Transport> {
+        Some(self)
+    }
+}
+
+impl Transport for FileSource {
+    fn position(&self) -> Duration {
+        self.control.lock().unwrap().position
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn is_paused(&self) -> bool {
+        self.control.lock().unwrap().paused
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        let mut control = self.control.lock().unwrap();
+        if !paused && control.ended {
+            control.seek = Some(Duration::ZERO);
+        }
+        control.ended = false;
+        control.paused = paused;
+    }
+
+    fn seek(&mut self, position: Duration) {
+        let mut control = self.control.lock().unwrap();
+        control.ended = false;
+        control.seek = Some(position);
+        control.position = position;
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{anyhow, bail, Result};
+use log::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use libpulse_binding::{
+    callbacks::ListResult,
+    channelmap::{Map, Position},
+    context::{self, Context, FlagSet},
+    def::BufferAttr,
+    mainloop::standard::{IterateResult, Mainloop},
+    operation,
+    sample::{Format, Spec},
+    stream::Direction,
+};
+use libpulse_simple_binding::Simple;
+
+use super::capture::{CaptureSource, PcmReader, BLOCK_FRAMES};
+use super::{ChannelLayout, Speaker};
+
+/// Sample rate captured at when the layout is given rather than the sink's.
+static SAMPLE_RATE: u32 = 48000;
+
+fn position(speaker: Speaker) -> Position {
+    match speaker {
+        Speaker::FrontLeft => Position::FrontLeft,
+        Speaker::FrontRight => Position::FrontRight,
+        Speaker::FrontCenter => Position::FrontCenter,
+        Speaker::LowFrequency => Position::Lfe,
+        Speaker::BackLeft => Position::RearLeft,
+        Speaker::BackRight => Position::RearRight,
+        Speaker::BackCenter => Position::RearCenter,
+        Speaker::SideLeft => Position::SideLeft,
+        Speaker::SideRight => Position::SideRight,
+        Speaker::TopFrontLeft => Position::TopFrontLeft,
+        Speaker::TopFrontRight => Position::TopFrontRight,
+        Speaker::TopBackLeft => Position::TopRearLeft,
+        Speaker::TopBackRight => Position::TopRearRight,
+    }
+}
+
+/// The speaker a sink channel feeds, if it is one the radar has a place for.
+fn speaker(position: Position) -> Option<Speaker> {
+    match position {
+        Position::Mono | Position::FrontCenter => Some(Speaker::FrontCenter),
+        Position::FrontLeft => Some(Speaker::FrontLeft),
+        Position::FrontRight => Some(Speaker::FrontRight),
+        Position::Lfe => Some(Speaker::LowFrequency),
+        Position::RearLeft => Some(Speaker::BackLeft),
+        Position::RearRight => Some(Speaker::BackRight),
+        Position::RearCenter => Some(Speaker::BackCenter),
+        Position::SideLeft => Some(Speaker::SideLeft),
+        Position::SideRight => Some(Speaker::SideRight),
+        Position::TopFrontLeft => Some(Speaker::TopFrontLeft),
+        Position::TopFrontRight => Some(Speaker::TopFrontRight),
+        Position::TopRearLeft => Some(Speaker::TopBackLeft),
+        Position::TopRearRight => Some(Speaker::TopBackRight),
+        _ => None,
+    }
+}
+
+fn iterate(mainloop: &mut Mainloop) -> Result<()> {
+    match mainloop.iterate(true) {
+        IterateResult::Success(_) => Ok(()),
+        IterateResult::Quit(_) => bail!("The PulseAudio main loop quit"),
+        IterateResult::Err(e) => bail!("The PulseAudio main loop failed: {}", e),
+    }
+}
+
+/// The sample rate and layout of `sink`, or of the default sink, asked of
+/// the server.
+fn sink_format(sink: Option<&str>) -> Result<(u32, ChannelLayout)> {
+    let name = sink.unwrap_or("@DEFAULT_SINK@");
+    let mut mainloop =
+        Mainloop::new().ok_or_else(|| anyhow!("Failed to create a PulseAudio main loop"))?;
+    let mut context = Context::new(&mainloop, "Panopticon")
+        .ok_or_else(|| anyhow!("Failed to create a PulseAudio context"))?;
+    context
+        .connect(None, FlagSet::NOFLAGS, None)
+        .map_err(|e| anyhow!("Failed to connect to PulseAudio: {}", e))?;
+    loop {
+        iterate(&mut mainloop)?;
+        match context.get_state() {
+            context::State::Ready => break,
+            context::State::Failed | context::State::Terminated => {
+                bail!("Failed to connect to PulseAudio")
+            }
+            _ => {}
+        }
+    }
+
+    let found = Rc::new(RefCell::new(None));
+    let operation = {
+        let found = found.clone();
+        context
+            .introspect()
+            .get_sink_info_by_name(name, move |result| {
+                if let ListResult::Item(info) = result {
+                    *found.borrow_mut() = Some((info.sample_spec, info.channel_map));
+                }
+            })
+    };
+    while operation.get_state() == operation::State::Running {
+        iterate(&mut mainloop)?;
+    }
+    context.disconnect();
+
+    let Some((spec, map)) = found.take() else {
+        bail!("No PulseAudio sink {}", name);
+    };
+    let speakers: Option<Vec<Speaker>> = map.get().iter().map(|p| speaker(*p)).collect();
+    let layout = match speakers {
+        Some(speakers) => ChannelLayout::new(speakers),
+        None => {
+            warn!(
+                "Sink {} has channels the radar has no place for ({}); \
+                 falling back to the default layout",
+                name,
+                map.print()
+            );
+            ChannelLayout::default_for(map.len() as usize).ok_or_else(|| {
+                anyhow!(
+                    "No default speaker layout for the {} channels of sink {}; give --layout",
+                    map.len(),
+                    name
+                )
+            })?
+        }
+    };
+    Ok((spec.rate, layout))
+}
+
+struct PulseReader {
+    stream: Simple,
+    bytes: Vec<u8>,
+}
+
+impl PcmReader for PulseReader {
+    fn read(&mut self, samples: &mut [f32]) -> Result<usize> {
+        let bytes = &mut self.bytes[..samples.len() * 4];
+        self.stream
+            .read(bytes)
+            .map_err(|e| anyhow!("Failed to read from PulseAudio: {}", e))?;
+        for (sample, bytes) in samples.iter_mut().zip(bytes.chunks_exact(4)) {
+            *sample = f32::from_ne_bytes(bytes.try_into().unwrap());
+        }
+        Ok(samples.len())
+    }
+}
+
+/// Capture the monitor of `sink`, or of the default sink, in the sink's own
+/// sample rate and channel map, or as `layout` if given.
+///
+/// PulseAudio and PipeWire remap whatever the sink carries onto the requested
+/// channel map, so a `layout` that differs from the sink's is up or down mixed.
+pub fn monitor(sink: Option<&str>, layout: Option<ChannelLayout>) -> Result<CaptureSource> {
+    let (sample_rate, layout) = match layout {
+        Some(layout) => (SAMPLE_RATE, layout),
+        None => sink_format(sink)?,
+    };
+    let channels = layout.len();
+    info!("Capturing {:?} at {}Hz", layout, sample_rate);
+    let device = match sink {
+        Some(sink) => format!("{}.monitor", sink),
+        None => "@DEFAULT_MONITOR@".to_string(),
+    };
+
+    let speakers = layout.speakers().to_vec();
+
+    CaptureSource::spawn("pulse", layout, sample_rate, move || {
+        let spec = Spec {
+            format: Format::FLOAT32NE,
+            channels: channels as u8,
+            rate: sample_rate,
+        };
+        let mut map = Map::default();
+        map.set_len(channels as u8);
+        for (position, speaker) in map.get_mut().iter_mut().zip(speakers) {
+            *position = self::position(speaker);
+        }
+        let block_bytes = (BLOCK_FRAMES * channels * 4) as u32;
+        let attr = BufferAttr {
+            maxlength: u32::MAX,
+            tlength: u32::MAX,
+            prebuf: u32::MAX,
+            minreq: u32::MAX,
+            fragsize: block_bytes,
+        };
+
+        info!("Connecting to PulseAudio source {}", device);
+        let stream = Simple::new(
+            None,
+            "Panopticon",
+            Direction::Record,
+            Some(&device),
+            "Radar",
+            &spec,
+            Some(&map),
+            Some(&attr),
+        )
+        .map_err(|e| anyhow!("Failed to capture {}: {}", device, e))?;
+
+        Ok(PulseReader {
+            stream,
+            bytes: vec![0; block_bytes as usize],
+        })
+    })
+}
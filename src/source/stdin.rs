@@ -0,0 +1,98 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use log::*;
+use std::io::{ErrorKind, Read};
+
+use super::capture::{CaptureSource, Pacer, PcmReader, BLOCK_FRAMES};
+use super::ChannelLayout;
+
+/// Encoding of raw samples, named as ffmpeg names them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SampleFormat {
+    F32le,
+    S16le,
+    S32le,
+}
+
+impl SampleFormat {
+    fn bytes(self) -> usize {
+        match self {
+            SampleFormat::F32le | SampleFormat::S32le => 4,
+            SampleFormat::S16le => 2,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            SampleFormat::F32le => f32::from_le_bytes(bytes.try_into().unwrap()),
+            SampleFormat::S16le => i16::from_le_bytes(bytes.try_into().unwrap()) as f32 / 32768.,
+            SampleFormat::S32le => {
+                i32::from_le_bytes(bytes.try_into().unwrap()) as f32 / 2147483648.
+            }
+        }
+    }
+}
+
+struct StdinReader {
+    format: SampleFormat,
+    bytes: Vec<u8>,
+    channels: usize,
+    pacer: Pacer,
+}
+
+impl PcmReader for StdinReader {
+    fn read(&mut self, samples: &mut [f32]) -> Result<usize> {
+        let bytes = &mut self.bytes[..samples.len() * self.format.bytes()];
+        let mut stdin = std::io::stdin().lock();
+        let mut filled = 0;
+        while filled < bytes.len() {
+            match stdin.read(&mut bytes[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // Drop any trailing partial frame at the end of the stream.
+        let frame_bytes = self.channels * self.format.bytes();
+        let read = filled / frame_bytes * self.channels;
+        for (sample, bytes) in samples
+            .iter_mut()
+            .zip(bytes.chunks_exact(self.format.bytes()))
+            .take(read)
+        {
+            *sample = self.format.decode(bytes);
+        }
+
+        // Piped files arrive faster than real time; live captures never get ahead.
+        self.pacer.wait(read / self.channels);
+        Ok(read)
+    }
+}
+
+/// Read interleaved raw PCM for `layout` from stdin, e.g. from
+/// `ffmpeg -f f32le -` or `pw-cat --record`.
+pub fn capture(
+    format: SampleFormat,
+    sample_rate: u32,
+    layout: ChannelLayout,
+) -> Result<CaptureSource> {
+    let channels = layout.len();
+    if sample_rate == 0 {
+        bail!("Sample rate must be positive");
+    }
+    info!(
+        "Reading {} channel {:?} at {}Hz from stdin",
+        channels, format, sample_rate
+    );
+
+    CaptureSource::spawn("stdin", layout, sample_rate, move || {
+        Ok(StdinReader {
+            format,
+            bytes: vec![0; BLOCK_FRAMES * channels * format.bytes()],
+            channels,
+            pacer: Pacer::new(sample_rate),
+        })
+    })
+}
@@ -0,0 +1,211 @@
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use log::*;
+
+use super::capture::{CaptureSource, Pacer, PcmReader};
+use super::ChannelLayout;
+
+static SAMPLE_RATE: u32 = 48000;
+/// Length in seconds of each burst in the burst scene.
+static BURST_LENGTH: f32 = 0.15;
+/// Fixed so that every run of a scene produces the same signal.
+static SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// A scripted test scene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Scene {
+    /// Noise circling the listener clockwise
+    Orbit,
+    /// Short bursts from one speaker at a time, stepping clockwise
+    Burst,
+    /// Independent pink noise on every channel
+    PinkNoise,
+    /// Noise wandering randomly around the listener
+    RandomWalk,
+}
+
+/// xorshift64*; small, fast and plenty for test signals.
+struct Rng(u64);
+
+impl Rng {
+    /// Uniformly distributed in -1..1.
+    fn uniform(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40;
+        bits as f32 / (1 << 23) as f32 - 1.
+    }
+}
+
+/// Paul Kellet's refined pink noise filter.
+#[derive(Default)]
+struct Pink([f32; 7]);
+
+impl Pink {
+    fn next(&mut self, white: f32) -> f32 {
+        let b = &mut self.0;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.016898;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * 0.11
+    }
+}
+
+/// Ear level channels of `layout` with their azimuths, clockwise from front.
+fn ring(layout: &ChannelLayout) -> Vec<(usize, f32)> {
+    let mut ring: Vec<(usize, f32)> = layout
+        .speakers()
+        .iter()
+        .enumerate()
+        .filter(|(_, speaker)| !speaker.is_overhead())
+        .filter_map(|(channel, speaker)| Some((channel, speaker.azimuth()?.rem_euclid(360.))))
+        .collect();
+    ring.sort_by(|a, b| a.1.total_cmp(&b.1));
+    ring
+}
+
+/// Constant power gains placing a source at `azimuth` between the two
+/// speakers of `ring` either side of it.
+fn pan(ring: &[(usize, f32)], azimuth: f32, gains: &mut [f32]) {
+    gains.fill(0.);
+    let azimuth = azimuth.rem_euclid(360.);
+    if ring.is_empty() {
+        return;
+    }
+    let after = ring.iter().position(|(_, a)| *a > azimuth).unwrap_or(0);
+    let before = (after + ring.len() - 1) % ring.len();
+    let (from, to) = (ring[before], ring[after]);
+    let span = (to.1 - from.1).rem_euclid(360.);
+    if span == 0. {
+        gains[from.0] = 1.;
+        return;
+    }
+    let fraction = (azimuth - from.1).rem_euclid(360.) / span;
+    let angle = fraction * std::f32::consts::FRAC_PI_2;
+    gains[from.0] += angle.cos();
+    gains[to.0] += angle.sin();
+}
+
+struct SynthReader {
+    scene: Scene,
+    /// Degrees per second.
+    speed: f32,
+    amplitude: f32,
+    channels: usize,
+    ring: Vec<(usize, f32)>,
+    rng: Rng,
+    pinks: Vec<Pink>,
+    gains: Vec<f32>,
+    azimuth: f32,
+    /// Degrees per second the random walk is currently drifting at.
+    drift: f32,
+    time: f32,
+    pacer: Pacer,
+}
+
+impl SynthReader {
+    /// Envelope of the burst scene at the current time, also steering `gains`.
+    fn burst(&mut self) -> f32 {
+        let slot = 360. / self.ring.len() as f32 / self.speed.max(f32::EPSILON);
+        let index = (self.time / slot) as usize % self.ring.len();
+        let (channel, _) = self.ring[index];
+        self.gains.fill(0.);
+        self.gains[channel] = 1.;
+
+        let t = self.time % slot;
+        if t < BURST_LENGTH {
+            (-t * 20.).exp()
+        } else {
+            0.
+        }
+    }
+}
+
+impl PcmReader for SynthReader {
+    fn read(&mut self, samples: &mut [f32]) -> Result<usize> {
+        let block = (samples.len() / self.channels) as f32 / SAMPLE_RATE as f32;
+
+        // Direction changes slowly enough to steer once per block.
+        let mut envelope = 1.;
+        match self.scene {
+            Scene::Orbit => {
+                self.azimuth = self.time * self.speed;
+                pan(&self.ring, self.azimuth, &mut self.gains);
+            }
+            Scene::RandomWalk => {
+                self.drift = (self.drift + self.rng.uniform() * self.speed * 0.2)
+                    .clamp(-self.speed, self.speed);
+                self.azimuth += self.drift * block;
+                pan(&self.ring, self.azimuth, &mut self.gains);
+            }
+            Scene::Burst => envelope = self.burst(),
+            Scene::PinkNoise => {}
+        }
+
+        for frame in samples.chunks_mut(self.channels) {
+            match self.scene {
+                Scene::PinkNoise => {
+                    for (sample, pink) in frame.iter_mut().zip(&mut self.pinks) {
+                        *sample = pink.next(self.rng.uniform()) * self.amplitude;
+                    }
+                }
+                _ => {
+                    let signal = self.pinks[0].next(self.rng.uniform()) * self.amplitude;
+                    for (sample, gain) in frame.iter_mut().zip(&self.gains) {
+                        *sample = signal * gain * envelope;
+                    }
+                }
+            }
+        }
+        self.time += block;
+
+        self.pacer.wait(samples.len() / self.channels);
+        Ok(samples.len())
+    }
+}
+
+/// Generate `scene` on `layout`.
+///
+/// `speed` is in degrees per second and `level` is the noise level in dBFS.
+pub fn generate(
+    scene: Scene,
+    layout: ChannelLayout,
+    speed: f32,
+    level: f32,
+) -> Result<CaptureSource> {
+    let channels = layout.len();
+    let ring = ring(&layout);
+    if ring.is_empty() {
+        bail!(
+            "The {} channel layout has no directional speakers",
+            channels
+        );
+    }
+    info!(
+        "Generating {:?} at {} degrees/s and {}dBFS",
+        scene, speed, level
+    );
+
+    CaptureSource::spawn("synth", layout, SAMPLE_RATE, move || {
+        Ok(SynthReader {
+            scene,
+            speed,
+            amplitude: 10f32.powf(level / 20.),
+            channels,
+            ring,
+            rng: Rng(SEED),
+            pinks: (0..channels).map(|_| Pink::default()).collect(),
+            gains: vec![0.; channels],
+            azimuth: 0.,
+            drift: 0.,
+            time: 0.,
+            pacer: Pacer::new(SAMPLE_RATE),
+        })
+    })
+}
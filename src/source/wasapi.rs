@@ -0,0 +1,239 @@
+use anyhow::{bail, Result};
+use log::*;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use windows::{
+    core::*, Win32::Media::Audio::Endpoints::IAudioMeterInformation, Win32::Media::Audio::*,
+    Win32::System::Com::*, Win32::UI::WindowsAndMessaging::*,
+};
+
+use super::capture::{CaptureSource, PcmReader};
+use super::{ChannelLayout, LevelSource};
+
+static WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+static WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+/// Length of the loopback capture buffer, in 100ns units.
+static LOOPBACK_BUFFER: i64 = 10_000_000;
+/// How long to wait for the next loopback packet when none is ready.
+static POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+fn get_default_endpoint() -> Result<IMMDevice> {
+    unsafe {
+        info!("Initializing COM");
+        let res = CoInitialize(None);
+        if res.is_err() {
+            let error = format!("Failed to init '{:?}'", res);
+            MessageBoxA(
+                None,
+                Some(PCSTR::from_raw(format!("{}\0", error).as_ptr())),
+                s!("Error"),
+                MB_OK,
+            );
+        }
+
+        info!("Creating instance");
+        let enumerator: IMMDeviceEnumerator =
+            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+
+        info!("Getting default endpoint");
+        let endpoint = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
+        info!("Getting endpoint id");
+
+        Ok(endpoint)
+    }
+}
+
+/// The parts of an endpoint's shared mode mix format we care about.
+#[derive(Clone, Copy, Debug)]
+struct MixFormat {
+    channels: usize,
+    sample_rate: u32,
+    bits: u16,
+    float: bool,
+    /// The channel mask, if the format has one.
+    mask: Option<u32>,
+}
+
+impl MixFormat {
+    /// Read the format `format` points to, which must be a valid `WAVEFORMATEX`.
+    unsafe fn read(format: *const WAVEFORMATEX) -> Self {
+        let header = std::ptr::read_unaligned(format);
+        let (float, mask) = if header.wFormatTag == WAVE_FORMAT_EXTENSIBLE {
+            let extensible = std::ptr::read_unaligned(format as *const WAVEFORMATEXTENSIBLE);
+            // The sub format GUIDs share their first field with the format tags
+            let float = extensible.SubFormat.data1 == WAVE_FORMAT_IEEE_FLOAT as u32;
+            (float, Some(extensible.dwChannelMask))
+        } else {
+            (header.wFormatTag == WAVE_FORMAT_IEEE_FLOAT, None)
+        };
+        Self {
+            channels: header.nChannels as usize,
+            sample_rate: header.nSamplesPerSec,
+            bits: header.wBitsPerSample,
+            float,
+            mask,
+        }
+    }
+}
+
+fn get_mix_format(endpoint: &IMMDevice) -> Result<MixFormat> {
+    unsafe {
+        let client: IAudioClient = endpoint.Activate(CLSCTX_ALL, None)?;
+        let format = client.GetMixFormat()?;
+        let mix_format = MixFormat::read(format);
+        CoTaskMemFree(Some(format as *const _));
+        Ok(mix_format)
+    }
+}
+
+/// The speaker layout of `channel_count` channels with channel mask `mask`,
+/// telling the user if there is none.
+fn get_layout(channel_count: usize, mask: Option<u32>) -> Result<ChannelLayout> {
+    info!("Mix format channel mask {:x?}", mask);
+
+    let layout = ChannelLayout::for_channels(channel_count, mask);
+    let Some(layout) = layout else {
+        let error = format!("No speaker layout is known for {} channels", channel_count);
+        unsafe {
+            MessageBoxA(
+                None,
+                Some(PCSTR::from_raw(format!("{}\0", error).as_ptr())),
+                s!("Error"),
+                MB_OK,
+            );
+        }
+        bail!(error);
+    };
+    Ok(layout)
+}
+
+/// Peak meter of the default WASAPI render endpoint.
+pub struct WasapiSource {
+    meter: IAudioMeterInformation,
+    layout: ChannelLayout,
+}
+
+impl WasapiSource {
+    pub fn default_endpoint() -> Result<Self> {
+        let endpoint = get_default_endpoint()?;
+        let meter: IAudioMeterInformation = unsafe { endpoint.Activate(CLSCTX_ALL, None)? };
+        info!("Got audio meter");
+
+        let channel_count = unsafe { meter.GetMeteringChannelCount()? } as usize;
+        let layout = get_layout(channel_count, get_mix_format(&endpoint)?.mask)?;
+        info!("Metering {} channels as {:?}", channel_count, layout);
+
+        Ok(Self { meter, layout })
+    }
+}
+
+impl LevelSource for WasapiSource {
+    fn layout(&self) -> &ChannelLayout {
+        &self.layout
+    }
+
+    fn read_levels(&mut self, levels: &mut [f32]) -> Result<()> {
+        unsafe { self.meter.GetChannelsPeakValues(levels)? };
+        Ok(())
+    }
+}
+
+/// Reads what the default render endpoint is playing through WASAPI loopback.
+struct LoopbackReader {
+    // Keeps the stream running
+    _client: IAudioClient,
+    capture: IAudioCaptureClient,
+    format: MixFormat,
+    /// Samples from the last packet that did not fit the caller's block.
+    pending: VecDeque<f32>,
+}
+
+impl LoopbackReader {
+    fn open() -> Result<Self> {
+        let endpoint = get_default_endpoint()?;
+        unsafe {
+            let client: IAudioClient = endpoint.Activate(CLSCTX_ALL, None)?;
+            let format = client.GetMixFormat()?;
+            let mix_format = MixFormat::read(format);
+            let initialized = client.Initialize(
+                AUDCLNT_SHAREMODE_SHARED,
+                AUDCLNT_STREAMFLAGS_LOOPBACK,
+                LOOPBACK_BUFFER,
+                0,
+                format,
+                None,
+            );
+            CoTaskMemFree(Some(format as *const _));
+            initialized?;
+            let capture: IAudioCaptureClient = client.GetService()?;
+            client.Start()?;
+            Ok(Self {
+                _client: client,
+                capture,
+                format: mix_format,
+                pending: VecDeque::new(),
+            })
+        }
+    }
+
+    /// Append the next packet to `pending`, if one is ready.
+    fn read_packet(&mut self) -> Result<bool> {
+        unsafe {
+            if self.capture.GetNextPacketSize()? == 0 {
+                return Ok(false);
+            }
+            let mut data = std::ptr::null_mut();
+            let mut frames = 0;
+            let mut flags = 0;
+            self.capture
+                .GetBuffer(&mut data, &mut frames, &mut flags, None, None)?;
+            let count = frames as usize * self.format.channels;
+            if flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0 {
+                self.pending.extend(std::iter::repeat_n(0., count));
+            } else if self.format.float {
+                let samples = std::slice::from_raw_parts(data as *const f32, count);
+                self.pending.extend(samples);
+            } else {
+                let samples = std::slice::from_raw_parts(data as *const i16, count);
+                self.pending
+                    .extend(samples.iter().map(|sample| *sample as f32 / 32768.));
+            }
+            self.capture.ReleaseBuffer(frames)?;
+            Ok(true)
+        }
+    }
+}
+
+impl PcmReader for LoopbackReader {
+    fn read(&mut self, samples: &mut [f32]) -> Result<usize> {
+        // Nothing arrives while the endpoint plays nothing, so just wait
+        while self.pending.len() < samples.len() {
+            if !self.read_packet()? {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+        let count = samples.len();
+        for (sample, pending) in samples.iter_mut().zip(self.pending.drain(..count)) {
+            *sample = pending;
+        }
+        Ok(count)
+    }
+}
+
+/// Capture what the default render endpoint plays, for analysis that needs
+/// the audio itself rather than its peak meter.
+pub fn loopback() -> Result<CaptureSource> {
+    let format = get_mix_format(&get_default_endpoint()?)?;
+    info!("Loopback mix format {:?}", format);
+    if !(format.float && format.bits == 32 || !format.float && format.bits == 16) {
+        bail!(
+            "Unsupported mix format of {} bit {} samples",
+            format.bits,
+            if format.float { "float" } else { "integer" }
+        );
+    }
+    let layout = get_layout(format.channels, format.mask)?;
+
+    CaptureSource::spawn("loopback", layout, format.sample_rate, LoopbackReader::open)
+}
@@ -0,0 +1,221 @@
+use eframe::{
+    egui,
+    epaint::{Color32, ColorImage, PathShape, Pos2, Rect, Stroke, TextureHandle},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::dsp::PowerSpectrum;
+use crate::settings::ScaleSettings;
+use crate::source::ChannelLayout;
+
+/// Samples per FFT; about 43ms at 48kHz.
+static FFT_SIZE: usize = 2048;
+/// Lowest frequency shown.
+static MIN_FREQUENCY: f32 = 20.;
+/// Quietest level measured, in dB below full scale.
+static RANGE_DB: f32 = 120.;
+/// Spectrogram columns kept, one per frame.
+static COLUMNS: usize = 160;
+/// Log spaced frequency rows in the spectrogram.
+static ROWS: usize = 64;
+/// Width of the panel beside the radar.
+pub static PANEL_WIDTH: f32 = 360.;
+
+/// How the spectrum of each channel is drawn.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrumSettings {
+    pub show: bool,
+    /// A scrolling spectrogram rather than the current spectrum.
+    pub spectrogram: bool,
+}
+
+/// Runs an FFT per channel and keeps a spectrogram of each.
+pub struct Spectrum {
+    sample_rate: u32,
+    channels: usize,
+    power: PowerSpectrum,
+    /// The latest `FFT_SIZE` samples of each channel.
+    recent: Vec<VecDeque<f32>>,
+    /// Level of each row in dB relative to full scale, by channel.
+    rows: Vec<Vec<f32>>,
+    /// Past `rows`, oldest first, by channel.
+    history: Vec<VecDeque<Vec<f32>>>,
+    textures: Vec<Option<TextureHandle>>,
+}
+
+impl Spectrum {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            power: PowerSpectrum::new(FFT_SIZE),
+            recent: vec![VecDeque::from(vec![0.; FFT_SIZE]); channels],
+            rows: vec![vec![-RANGE_DB; ROWS]; channels],
+            history: vec![VecDeque::new(); channels],
+            textures: vec![None; channels],
+        }
+    }
+
+    /// The frequency at the bottom edge of `row`, which may be `ROWS`.
+    fn row_frequency(&self, row: usize) -> f32 {
+        let nyquist = self.sample_rate as f32 / 2.;
+        MIN_FREQUENCY * (nyquist / MIN_FREQUENCY).powf(row as f32 / ROWS as f32)
+    }
+
+    /// Position of `frequency` along the frequency axis of a plot, from 0 to 1.
+    fn frequency_position(&self, frequency: f32) -> f32 {
+        let nyquist = self.sample_rate as f32 / 2.;
+        (frequency / MIN_FREQUENCY).ln() / (nyquist / MIN_FREQUENCY).ln()
+    }
+
+    /// Analyse the interleaved `samples` captured since the last update.
+    pub fn update(&mut self, samples: &[f32]) {
+        if samples.is_empty() {
+            return;
+        }
+        for frame in samples.chunks_exact(self.channels) {
+            for (recent, sample) in self.recent.iter_mut().zip(frame) {
+                recent.pop_front();
+                recent.push_back(*sample);
+            }
+        }
+
+        let bin_width = self.sample_rate as f32 / FFT_SIZE as f32;
+        // Scale so that a full scale sine reads 0dB
+        let scale = 4. / FFT_SIZE as f32;
+        let edges: Vec<usize> = (0..=ROWS)
+            .map(|row| (self.row_frequency(row) / bin_width) as usize)
+            .collect();
+        for channel in 0..self.channels {
+            let power = self.power.process(&self.recent[channel]);
+
+            let rows: Vec<f32> = (0..ROWS)
+                .map(|row| {
+                    let low = edges[row];
+                    let high = edges[row + 1].clamp(low + 1, power.len());
+                    let peak = power[low.min(power.len() - 1)..high]
+                        .iter()
+                        .map(|bin| bin.sqrt() * scale)
+                        .fold(0., f32::max);
+                    (20. * peak.max(1e-9).log10()).max(-RANGE_DB)
+                })
+                .collect();
+            let history = &mut self.history[channel];
+            if history.len() == COLUMNS {
+                history.pop_front();
+            }
+            history.push_back(rows.clone());
+            self.rows[channel] = rows;
+        }
+    }
+
+    /// Colour of a level on the spectrogram, from black through red to yellow.
+    fn color(level_db: f32, scale: &ScaleSettings) -> Color32 {
+        let level = scale.scale_db(level_db);
+        Color32::from_rgb(
+            ((level * 2.).min(1.) * 255.) as u8,
+            ((level * 2. - 1.).max(0.) * 255.) as u8,
+            0,
+        )
+    }
+
+    fn spectrogram(&mut self, ui: &egui::Ui, channel: usize, rect: Rect, scale: &ScaleSettings) {
+        let mut image = ColorImage::new([COLUMNS, ROWS], Color32::BLACK);
+        let offset = COLUMNS - self.history[channel].len();
+        for (column, rows) in self.history[channel].iter().enumerate() {
+            for (row, level) in rows.iter().enumerate() {
+                // Low frequencies at the bottom
+                image[(offset + column, ROWS - 1 - row)] = Self::color(*level, scale);
+            }
+        }
+        let texture = match &mut self.textures[channel] {
+            Some(texture) => {
+                texture.set(image, Default::default());
+                texture
+            }
+            texture => texture.insert(ui.ctx().load_texture(
+                format!("spectrogram {}", channel),
+                image,
+                Default::default(),
+            )),
+        };
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1., 1.));
+        ui.painter().image(texture.id(), rect, uv, Color32::WHITE);
+    }
+
+    fn spectrum(&self, ui: &egui::Ui, channel: usize, rect: Rect, scale: &ScaleSettings) {
+        let points = self.rows[channel]
+            .iter()
+            .enumerate()
+            .map(|(row, level)| {
+                let x = (row as f32 + 0.5) / ROWS as f32;
+                let y = 1. - scale.scale_db(*level);
+                Pos2::new(
+                    rect.left() + x * rect.width(),
+                    rect.top() + y * rect.height(),
+                )
+            })
+            .collect();
+        ui.painter().add(PathShape::line(
+            points,
+            Stroke {
+                width: 1.,
+                color: Color32::LIGHT_GREEN,
+            },
+        ));
+    }
+
+    /// Draw a plot per channel, marking the corners of `band` if any.
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        layout: &ChannelLayout,
+        settings: &mut SpectrumSettings,
+        scale: &ScaleSettings,
+        band: Option<(f32, f32)>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut settings.spectrogram, false, "Spectrum");
+            ui.selectable_value(&mut settings.spectrogram, true, "Spectrogram");
+        });
+
+        let height = ui.available_height() / self.channels as f32;
+        for channel in 0..self.channels {
+            let (rect, _) = ui.allocate_exact_size(
+                egui::vec2(ui.available_width(), height),
+                egui::Sense::hover(),
+            );
+            let plot = rect.shrink(2.);
+            ui.painter().rect_filled(plot, 0., Color32::BLACK);
+            if settings.spectrogram {
+                self.spectrogram(ui, channel, plot, scale);
+            } else {
+                self.spectrum(ui, channel, plot, scale);
+            }
+
+            let stroke = Stroke {
+                width: 1.,
+                color: Color32::LIGHT_BLUE,
+            };
+            for frequency in band.iter().flat_map(|(low, high)| [*low, *high]) {
+                let position = self.frequency_position(frequency);
+                if settings.spectrogram {
+                    let y = plot.bottom() - position * plot.height();
+                    ui.painter().hline(plot.x_range(), y, stroke);
+                } else {
+                    let x = plot.left() + position * plot.width();
+                    ui.painter().vline(x, plot.y_range(), stroke);
+                }
+            }
+            ui.painter().text(
+                plot.left_top() + egui::vec2(2., 2.),
+                egui::Align2::LEFT_TOP,
+                format!("{:?}", layout.speakers()[channel]),
+                egui::FontId::monospace(10.),
+                Color32::WHITE,
+            );
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{bail, Result};
+use log::*;
+use std::path::Path;
+
+use crate::dsp::PowerSpectrum;
+use crate::source::file;
+
+/// Samples per FFT when fingerprinting.
+static FFT_SIZE: usize = 1024;
+/// Fingerprint frames per second, whatever the sample rate.
+static FRAME_RATE: f32 = 100.;
+/// Log spaced bands in each fingerprint frame, and the range they span in Hz.
+static BANDS: usize = 24;
+static BAND_RANGE: (f32, f32) = (100., 10000.);
+/// Range of levels below the loudest that a fingerprint keeps, so that the
+/// silence in a clean recording compares equal to background noise.
+static DYNAMIC_RANGE_DB: f32 = 40.;
+/// Template audio quieter than this relative to its peak, before the sound
+/// starts, is trimmed so templates line up with event onsets.
+static LEAD_IN_DB: f32 = 30.;
+/// Longest template used; events only keep a second of audio.
+static MAX_FRAMES: usize = 100;
+/// How many frames either side of the onset a template may start, to allow
+/// for onsets being detected at slightly different points in the sound.
+static SEARCH: isize = 5;
+
+/// Log band energies of `samples` at `sample_rate`, one row per frame.
+fn fingerprint(samples: &[f32], sample_rate: u32) -> Vec<Vec<f32>> {
+    let mut spectrum = PowerSpectrum::new(FFT_SIZE);
+    let hop = ((sample_rate as f32 / FRAME_RATE) as usize).max(1);
+    let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+    let edges: Vec<usize> = (0..=BANDS)
+        .map(|band| {
+            let frequency =
+                BAND_RANGE.0 * (BAND_RANGE.1 / BAND_RANGE.0).powf(band as f32 / BANDS as f32);
+            ((frequency / bin_width) as usize).min(FFT_SIZE / 2)
+        })
+        .collect();
+
+    let mut frames: Vec<Vec<f32>> = (0..samples.len().saturating_sub(FFT_SIZE / 2))
+        .step_by(hop)
+        .map(|start| {
+            let power = spectrum.process(&samples[start..]);
+            edges
+                .windows(2)
+                .map(|edge| {
+                    let power: f32 = power[edge[0]..edge[1].max(edge[0] + 1)].iter().sum();
+                    10. * power.max(1e-12).log10()
+                })
+                .collect()
+        })
+        .collect();
+
+    let floor = frames.iter().flatten().fold(f32::MIN, |a, b| a.max(*b)) - DYNAMIC_RANGE_DB;
+    for band in frames.iter_mut().flatten() {
+        *band = band.max(floor);
+    }
+    frames
+}
+
+/// Pearson correlation of `template` with `clip` shifted by `offset` frames,
+/// over the frames they overlap.
+fn correlation(template: &[Vec<f32>], clip: &[Vec<f32>], offset: isize) -> Option<f32> {
+    let (template, clip) = if offset < 0 {
+        (template.get(offset.unsigned_abs()..)?, clip)
+    } else {
+        (template, clip.get(offset as usize..)?)
+    };
+    let frames = template.len().min(clip.len());
+    // Too little of the template heard to tell
+    if frames < template.len().div_ceil(2) {
+        return None;
+    }
+    let cells = || {
+        template[..frames]
+            .iter()
+            .flatten()
+            .zip(clip[..frames].iter().flatten())
+    };
+    let count = (frames * BANDS) as f32;
+    let (mean_t, mean_c) = cells().fold((0., 0.), |(t, c), (a, b)| (t + a, c + b));
+    let (mean_t, mean_c) = (mean_t / count, mean_c / count);
+    let (mut covariance, mut variance_t, mut variance_c) = (0., 0., 0.);
+    for (t, c) in cells() {
+        covariance += (t - mean_t) * (c - mean_c);
+        variance_t += (t - mean_t) * (t - mean_t);
+        variance_c += (c - mean_c) * (c - mean_c);
+    }
+    Some(covariance / (variance_t * variance_c).sqrt().max(1e-12))
+}
+
+/// A recording of a sound to look for.
+struct Template {
+    name: String,
+    fingerprint: Vec<Vec<f32>>,
+}
+
+/// Recognises specific sounds by comparing the spectra of events with those
+/// of short recordings.
+#[derive(Default)]
+pub struct TemplateMatcher {
+    templates: Vec<Template>,
+}
+
+impl TemplateMatcher {
+    /// Load the recordings at `paths`, each named after its file.
+    pub fn load(paths: &[impl AsRef<Path>]) -> Result<Self> {
+        let templates = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref();
+                let (samples, sample_rate, channels) = file::decode(path)?;
+                let mono: Vec<f32> = samples
+                    .chunks_exact(channels)
+                    .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                    .collect();
+                let mut fingerprint = fingerprint(&mono, sample_rate);
+
+                // Start at the onset, as events do
+                let loudest = fingerprint
+                    .iter()
+                    .map(|frame| frame.iter().fold(f32::MIN, |a, b| a.max(*b)))
+                    .fold(f32::MIN, f32::max);
+                let onset = fingerprint
+                    .iter()
+                    .position(|frame| frame.iter().any(|band| *band > loudest - LEAD_IN_DB))
+                    .unwrap_or(0);
+                fingerprint.drain(..onset);
+                fingerprint.truncate(MAX_FRAMES);
+                if fingerprint.is_empty() {
+                    bail!("{} is too short to match", path.display());
+                }
+
+                let name = path.file_stem().map_or_else(
+                    || path.display().to_string(),
+                    |stem| stem.to_string_lossy().into_owned(),
+                );
+                info!(
+                    "Loaded template {} of {}ms",
+                    name,
+                    fingerprint.len() as f32 * 1000. / FRAME_RATE
+                );
+                Ok(Template { name, fingerprint })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { templates })
+    }
+
+    /// The template the mono `clip` from an event's onset best matches, and
+    /// how well, if any matches at least `threshold`. The clip must be
+    /// broadband, as the templates are, not limited by the frequency filter.
+    pub fn best_match(
+        &self,
+        clip: &[f32],
+        sample_rate: u32,
+        threshold: f32,
+    ) -> Option<(&str, f32)> {
+        if self.templates.is_empty() {
+            return None;
+        }
+        let clip = fingerprint(clip, sample_rate);
+        self.templates
+            .iter()
+            .filter_map(|template| {
+                let score = (-SEARCH..=SEARCH)
+                    .filter_map(|offset| correlation(&template.fingerprint, &clip, offset))
+                    .fold(f32::MIN, f32::max);
+                (score >= threshold).then_some((template.name.as_str(), score))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::direction::{self, Direction};
+use crate::distance;
+use crate::dsp::Biquad;
+use crate::mapping::ChannelMap;
+use crate::placement::SpeakerPlacement;
+use crate::settings::DistanceSettings;
+use crate::source::ChannelLayout;
+
+/// Octave bands analysed separately, so that sounds in different parts of the
+/// spectrum are located independently.
+static BAND_CENTRES: [f32; 7] = [125., 250., 500., 1000., 2000., 4000., 8000.];
+/// The Q of an octave wide band-pass.
+static BAND_Q: f32 = 1.414;
+/// Ratio of an octave band's edges to its centre.
+static BAND_EDGE: f32 = std::f32::consts::SQRT_2;
+/// Candidates from different bands closer than this are taken as one sound.
+static MERGE_ANGLE: f32 = 30.;
+/// Furthest a track may move between frames and still match a sound.
+static GATE_ANGLE: f32 = 45.;
+/// How much of each new position is taken into a track, smoothing jitter.
+static SMOOTHING: f32 = 0.5;
+/// How long a track survives without a matching sound.
+static HOLD: Duration = Duration::from_millis(500);
+/// How far back a track's trail reaches.
+pub static TRAIL: Duration = Duration::from_millis(1500);
+
+/// Degrees from `a` to `b` the short way round, in -180..=180.
+fn angle_between(a: f32, b: f32) -> f32 {
+    (b - a + 180.).rem_euclid(360.) - 180.
+}
+
+/// Splits captured audio into octave bands and measures each channel in each.
+pub struct BandAnalyser {
+    channels: usize,
+    /// Centre frequency of each band analysed.
+    centres: Vec<f32>,
+    /// Filters by band, then by channel.
+    filters: Vec<Vec<Biquad>>,
+}
+
+impl BandAnalyser {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let centres: Vec<f32> = BAND_CENTRES
+            .iter()
+            .copied()
+            .filter(|centre| *centre < sample_rate as f32 * 0.4)
+            .collect();
+        let filters = centres
+            .iter()
+            .map(|centre| vec![Biquad::band_pass(sample_rate, *centre, BAND_Q); channels])
+            .collect();
+        Self {
+            channels,
+            centres,
+            filters,
+        }
+    }
+
+    /// RMS level of each channel in each band over the interleaved `samples`,
+    /// or `None` if there are none.
+    pub fn analyse(&mut self, samples: &[f32]) -> Option<Vec<Vec<f32>>> {
+        let frames = samples.len() / self.channels;
+        if frames == 0 {
+            return None;
+        }
+        let mut levels = vec![vec![0.; self.channels]; self.filters.len()];
+        for (band, filters) in self.filters.iter_mut().enumerate() {
+            for frame in samples.chunks_exact(self.channels) {
+                for ((sum, filter), sample) in
+                    levels[band].iter_mut().zip(filters.iter_mut()).zip(frame)
+                {
+                    let y = filter.process(*sample);
+                    *sum += y * y;
+                }
+            }
+            for level in &mut levels[band] {
+                *level = (*level / frames as f32).sqrt();
+            }
+        }
+        Some(levels)
+    }
+}
+
+/// A sound followed across frames.
+pub struct Track {
+    pub id: u32,
+    pub direction: Direction,
+    /// Estimated relative distance, from 0 near to 1 far.
+    pub distance: f32,
+    last_seen: Instant,
+    /// Past azimuths and distances, oldest first.
+    pub trail: VecDeque<(Instant, f32, f32)>,
+}
+
+/// Combine candidate directions lying close together, loudest first, into
+/// one per sound.
+fn merge(mut candidates: Vec<Direction>) -> Vec<Direction> {
+    candidates.sort_by(|a, b| b.level.total_cmp(&a.level));
+    let mut merged: Vec<(Direction, f32)> = Vec::new();
+    for candidate in candidates {
+        let energy = candidate.level * candidate.level;
+        match merged
+            .iter_mut()
+            .find(|(sound, _)| angle_between(sound.azimuth, candidate.azimuth).abs() < MERGE_ANGLE)
+        {
+            Some((sound, total)) => {
+                // Move the sound towards the candidate by its share of the energy
+                let offset = angle_between(sound.azimuth, candidate.azimuth);
+                *total += energy;
+                sound.azimuth += offset * energy / *total;
+                sound.focus = sound.focus.max(candidate.focus);
+                sound.level = total.sqrt().min(1.);
+            }
+            None => merged.push((candidate, energy)),
+        }
+    }
+    merged.into_iter().map(|(sound, _)| sound).collect()
+}
+
+/// Follows several concurrent sounds, giving each a persistent ID.
+#[derive(Default)]
+pub struct Tracker {
+    analyser: Option<BandAnalyser>,
+    tracks: Vec<Track>,
+    next_id: u32,
+}
+
+impl Tracker {
+    /// A tracker for audio at `sample_rate`, or for levels alone if `None`.
+    pub fn new(sample_rate: Option<u32>, channels: usize) -> Self {
+        Self {
+            analyser: sample_rate.map(|rate| BandAnalyser::new(rate, channels)),
+            ..Default::default()
+        }
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Locate the sounds in the interleaved broadband `samples` since the
+    /// last update, only in the octave bands overlapping `band` if given, or
+    /// in `levels` for sources without audio, and match them to the tracks
+    /// at `now`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        now: Instant,
+        layout: &ChannelLayout,
+        placement: &SpeakerPlacement,
+        channel_map: &ChannelMap,
+        levels: &[f32],
+        samples: &[f32],
+        band: Option<(f32, f32)>,
+        threshold: f32,
+        distance_settings: &DistanceSettings,
+    ) {
+        let (bands, located) = match &mut self.analyser {
+            Some(analyser) => {
+                let bands = analyser.analyse(samples).unwrap_or_default();
+                let located = analyser
+                    .centres
+                    .iter()
+                    .map(|centre| {
+                        band.is_none_or(|(low, high)| {
+                            centre * BAND_EDGE > low && centre / BAND_EDGE < high
+                        })
+                    })
+                    .collect();
+                (bands, located)
+            }
+            None => (vec![levels.to_vec()], vec![true]),
+        };
+        let candidates = bands
+            .iter()
+            .zip(located)
+            .filter(|(_, located)| *located)
+            .flat_map(|(band, _)| {
+                direction::separate(layout, placement, channel_map, band, threshold)
+            })
+            .collect();
+        let mut sounds = merge(candidates);
+
+        // Match the loudest sounds first, each to the nearest free track
+        let mut matched = vec![false; self.tracks.len()];
+        sounds.sort_by(|a, b| b.level.total_cmp(&a.level));
+        for sound in sounds {
+            // Only sources with audio show how much top end a sound has lost
+            // and how reverberant it is
+            let rolloff_db = self.analyser.as_ref().and_then(|analyser| {
+                distance::rolloff_db(
+                    layout,
+                    placement,
+                    channel_map,
+                    &analyser.centres,
+                    &bands,
+                    sound.azimuth,
+                )
+            });
+            let direct_ratio_db = self.analyser.as_ref().and_then(|_| {
+                distance::direct_ratio_db(layout, placement, channel_map, samples, sound.azimuth)
+            });
+            let distance =
+                distance::estimate(&sound, rolloff_db, direct_ratio_db, distance_settings);
+            let nearest = self
+                .tracks
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !matched[*i])
+                .map(|(i, track)| (i, angle_between(track.direction.azimuth, sound.azimuth)))
+                .filter(|(_, offset)| offset.abs() < GATE_ANGLE)
+                .min_by(|a, b| a.1.abs().total_cmp(&b.1.abs()));
+            match nearest {
+                Some((i, offset)) => {
+                    matched[i] = true;
+                    let track = &mut self.tracks[i];
+                    let previous = track.direction.azimuth;
+                    track.trail.push_back((now, previous, track.distance));
+                    track.direction = Direction {
+                        azimuth: (previous + offset * SMOOTHING + 180.).rem_euclid(360.) - 180.,
+                        ..sound
+                    };
+                    track.distance += (distance - track.distance) * SMOOTHING;
+                    track.last_seen = now;
+                }
+                None => {
+                    self.tracks.push(Track {
+                        id: self.next_id,
+                        direction: sound,
+                        distance,
+                        last_seen: now,
+                        trail: VecDeque::new(),
+                    });
+                    self.next_id += 1;
+                }
+            }
+        }
+
+        self.tracks.retain(|track| now - track.last_seen < HOLD);
+        for track in &mut self.tracks {
+            while track
+                .trail
+                .front()
+                .is_some_and(|(time, _, _)| now - *time > TRAIL)
+            {
+                track.trail.pop_front();
+            }
+        }
+    }
+}